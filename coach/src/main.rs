@@ -1,32 +1,51 @@
-use rs_frame::{App, AppPath, Controller, RouteParams};
+use rs_frame::{App, AppPath, Controller, PathController, RouteParams};
 use serde::{Deserialize, Serialize};
 
+#[derive(AppPath, Debug)]
+enum Route {
+    #[path("/home")]
+    Home,
+
+    EnvironmentDetail(EnvironmentDetailPath),
+
+    ExpiredSubmissions(ExpiredSubmissionsPath),
+}
+
+#[derive(AppPath, Debug)]
+#[path("/environments/:env_id")]
+struct EnvironmentDetailPath {
+    env_id: String,
+}
+
+/// What the controllers ask `main` to do
+#[derive(Debug)]
+enum Command {
+    LoadEnvironment(String),
+    LoadHomeScreen,
+}
+
 #[derive(Default, Hash)]
 struct EnvironmentDetailController {
     env_id: String,
 }
 
-impl Controller for EnvironmentDetailController {
-    fn params(&mut self, params: &RouteParams) -> Option<()> {
-        if params.data.name.starts_with("/environments/") {
-            let env_id = params.data.name.rsplit("/").next().unwrap();
-            self.env_id = env_id.to_string();
+impl PathController for EnvironmentDetailController {
+    type Path = EnvironmentDetailPath;
+    type Effect = Command;
 
-            Some(())
-        } else {
-            None
-        }
+    fn params(&mut self, path: &EnvironmentDetailPath) {
+        self.env_id = path.env_id.clone();
     }
 
-    fn start(&self) -> Vec<String> {
+    fn start(&self) -> Vec<Command> {
         println!(
             "Environment detail controller starting with env_id: {}",
             self.env_id
         );
-        vec![format!("load env {}", self.env_id)]
+        vec![Command::LoadEnvironment(self.env_id.clone())]
     }
 
-    fn stop(&self) -> Vec<String> {
+    fn stop(&self) -> Vec<Command> {
         println!("Environment detail controller stopping");
         vec![]
     }
@@ -36,17 +55,18 @@ impl Controller for EnvironmentDetailController {
 struct HomeController;
 
 impl Controller for HomeController {
+    type Effect = Command;
+
     fn params(&mut self, params: &RouteParams) -> Option<()> {
-        if params.data.name == "/home" {
-            Some(())
-        } else {
-            None
+        match *params.path::<Route>()? {
+            Route::Home => Some(()),
+            _ => None,
         }
     }
 
-    fn start(&self) -> Vec<String> {
+    fn start(&self) -> Vec<Command> {
         println!("Home controller start!");
-        vec![format!("load the home screen")]
+        vec![Command::LoadHomeScreen]
     }
 }
 
@@ -225,11 +245,22 @@ fn main() {
     // println!("Query string: {}", email_path.query_string());
 
     let mut app = App::new();
-    app.add_controller(EnvironmentDetailController::default());
+    app.add_path_controller(EnvironmentDetailController::default());
     app.add_controller(HomeController);
-
-    app.new_route("/environments/exam-copy".to_string());
-    app.new_route("/environments/deadline-extension".to_string());
-    app.new_route("/home".to_string());
-    app.new_route("/home".to_string());
+    app.set_effect_handler(|command: &Command| match command {
+        Command::LoadEnvironment(env_id) => println!("Loading environment {}", env_id),
+        Command::LoadHomeScreen => println!("Loading the home screen"),
+    });
+
+    let routes = [
+        "/environments/exam-copy",
+        "/environments/deadline-extension",
+        "/home",
+        "/home",
+    ];
+
+    for route in &routes {
+        println!("new route: {}", route);
+        app.new_route(route.to_string());
+    }
 }
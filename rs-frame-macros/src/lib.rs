@@ -5,7 +5,7 @@ use proc_macro::TokenStream;
 use proc_macro2;
 use quote::quote;
 use regex::Regex;
-use std::collections::HashSet;
+use std::fmt;
 use syn::{parse_macro_input, DeriveInput};
 
 #[derive(Debug, PartialEq)]
@@ -14,13 +14,140 @@ enum PathToRegexError {
     NonAsciiChars,
     InvalidIdentifier(String),
     InvalidTrailingSlash,
+    SplatNotAtEnd(String),
+    InvalidOptionalParam(String),
+    UnterminatedConstraint(String),
+    InvalidConstraint(String),
+    ConstraintHasCaptureGroups(String),
 }
 
-fn path_to_regex(path: &str) -> Result<(String, String), PathToRegexError> {
+impl fmt::Display for PathToRegexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PathToRegexError::MissingLeadingForwardSlash => write!(f, "path must start with a '/'"),
+            PathToRegexError::NonAsciiChars => write!(f, "path must only contain ASCII characters"),
+            PathToRegexError::InvalidIdentifier(name) => write!(
+                f,
+                "`{}` is not a valid path parameter name, it must be a Rust identifier starting with a letter",
+                name
+            ),
+            PathToRegexError::InvalidTrailingSlash => write!(f, "path must not end with a '/'"),
+            PathToRegexError::SplatNotAtEnd(name) => write!(
+                f,
+                "splat parameter `*{}` must be at the end of the path",
+                name
+            ),
+            PathToRegexError::InvalidOptionalParam(name) => write!(
+                f,
+                "optional parameter `:{}?` must make up a whole path segment",
+                name
+            ),
+            PathToRegexError::UnterminatedConstraint(name) => write!(
+                f,
+                "constraint for parameter `{}` is missing a closing ')'",
+                name
+            ),
+            PathToRegexError::InvalidConstraint(constraint) => {
+                write!(f, "constraint `{}` is not a valid regex", constraint)
+            }
+            PathToRegexError::ConstraintHasCaptureGroups(constraint) => write!(
+                f,
+                "constraint `{}` must not contain capture groups, use `(?:...)` instead",
+                constraint
+            ),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum ParamKind {
+    /// `:name` - matches a single path segment
+    Segment,
+    /// `*name` - matches the rest of the path, slashes included
+    Splat,
+}
+
+#[derive(Debug, PartialEq)]
+struct PathParam {
+    name: String,
+    kind: ParamKind,
+    /// `:name?` - the segment, along with its leading slash, may be left out
+    optional: bool,
+    /// `:name(regex)` - replaces the default pattern for the param
+    constraint: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+enum PathToken {
+    /// Static text, matched as-is
+    Literal(String),
+    /// Index into `PathPattern::params`
+    Param(usize),
+}
+
+#[derive(Debug, PartialEq)]
+struct PathPattern {
+    regex: String,
+    format_str: String,
+    params: Vec<PathParam>,
+    /// The path split up into static text and params, in order
+    tokens: Vec<PathToken>,
+}
+
+const REGEX_META_CHARS: &str = r"\.+*?()|[]{}^$";
+
+impl PathPattern {
+    fn push_static(&mut self, byte: char) {
+        // Static text is matched literally, and braces have to be
+        // doubled up so `format!` doesn't treat them as arguments
+        if REGEX_META_CHARS.contains(byte) {
+            self.regex.push('\\');
+        }
+
+        self.regex.push(byte);
+
+        match byte {
+            '{' => self.format_str += "{{",
+            '}' => self.format_str += "}}",
+            _ => self.format_str.push(byte),
+        }
+
+        match self.tokens.last_mut() {
+            Some(PathToken::Literal(literal)) => literal.push(byte),
+            _ => self.tokens.push(PathToken::Literal(byte.to_string())),
+        }
+    }
+
+    fn pop_static(&mut self) -> Option<char> {
+        let byte = self.regex.pop();
+        self.format_str.pop();
+
+        if let Some(PathToken::Literal(literal)) = self.tokens.last_mut() {
+            literal.pop();
+
+            if literal.is_empty() {
+                self.tokens.pop();
+            }
+        }
+
+        byte
+    }
+}
+
+fn path_to_regex(path: &str) -> Result<PathPattern, PathToRegexError> {
     enum ParseState {
         Initial,
         Static,
-        VarName(String),
+        ParamName(String, ParamKind),
+        Constraint {
+            name: String,
+            kind: ParamKind,
+            constraint: String,
+            depth: usize,
+            escaped: bool,
+        },
+        ConstraintEnd(String, ParamKind, String),
+        SegmentEnd(String),
     };
 
     if !path.is_ascii() {
@@ -29,67 +156,201 @@ fn path_to_regex(path: &str) -> Result<(String, String), PathToRegexError> {
 
     let ident_regex = Regex::new(r"^[a-zA-Z][a-zA-Z0-9_]*$").unwrap();
 
-    let mut regex = "".to_string();
-    let mut format_str = "".to_string();
+    let mut pattern = PathPattern {
+        regex: "^".to_string(),
+        format_str: "".to_string(),
+        params: vec![],
+        tokens: vec![],
+    };
     let mut parse_state = ParseState::Initial;
 
-    for byte in path.chars() {
-        match parse_state {
-            ParseState::Initial => {
-                if byte != '/' {
-                    return Err(PathToRegexError::MissingLeadingForwardSlash);
-                }
+    // Called with the character which ended the param, or None at the end of the path.
+    // Apart from a '?', that character still needs to be parsed as part of the path.
+    let end_param = |pattern: &mut PathPattern,
+                     name: String,
+                     kind: ParamKind,
+                     constraint: Option<String>,
+                     end: Option<char>| {
+        // Validate 'name' as a Rust identifier
+        if !ident_regex.is_match(&name) {
+            return Err(PathToRegexError::InvalidIdentifier(name));
+        }
+
+        if kind == ParamKind::Splat && end.is_some() {
+            // A splat swallows everything after it, so nothing can follow
+            return Err(PathToRegexError::SplatNotAtEnd(name));
+        }
 
-                regex += "^/";
-                format_str += "/";
+        if let Some(ref constraint) = constraint {
+            let constraint_regex = Regex::new(constraint)
+                .map_err(|_| PathToRegexError::InvalidConstraint(constraint.clone()))?;
 
-                parse_state = ParseState::Static;
+            // Group 0 is the whole match, anything more would shift the named captures
+            if constraint_regex.captures_len() > 1 {
+                return Err(PathToRegexError::ConstraintHasCaptureGroups(
+                    constraint.clone(),
+                ));
             }
-            ParseState::Static => {
-                if byte == ':' {
-                    format_str.push('{');
-                    parse_state = ParseState::VarName("".to_string());
-                } else {
-                    regex.push(byte);
-                    format_str.push(byte);
+        }
+
+        let optional = end == Some('?');
+
+        // Literal text after the param in the same segment, like the '-' in
+        // `:id-:slug`, ends the param at its first occurrence
+        let ends_mid_segment = !optional && end.is_some() && end != Some('/');
+
+        // Splats and optional params can be empty, since an empty `Vec` or
+        // `Some("")` is written out that way
+        let param_regex = match (&constraint, &kind) {
+            (Some(constraint), _) => format!("(?:{})", constraint),
+            (None, ParamKind::Segment) if optional => "[^/]*".to_string(),
+            (None, ParamKind::Segment) if ends_mid_segment => "[^/]+?".to_string(),
+            (None, ParamKind::Segment) => "[^/]+".to_string(),
+            (None, ParamKind::Splat) => ".*".to_string(),
+        };
+
+        if optional {
+            // The leading slash is only written out when the param is present
+            if pattern.pop_static() != Some('/') {
+                return Err(PathToRegexError::InvalidOptionalParam(name));
+            }
+
+            pattern.regex += &format!("(?:/(?P<{}>{}))?", name, param_regex);
+        } else {
+            pattern.regex += &format!("(?P<{}>{})", name, param_regex);
+        }
+
+        pattern.format_str += &format!("{{{}}}", name);
+        pattern.tokens.push(PathToken::Param(pattern.params.len()));
+        pattern.params.push(PathParam {
+            name: name.clone(),
+            kind,
+            optional,
+            constraint,
+        });
+
+        if optional {
+            Ok(ParseState::SegmentEnd(name))
+        } else {
+            Ok(ParseState::Static)
+        }
+    };
+
+    let is_ident_char = |byte: char| byte.is_ascii_alphanumeric() || byte == '_';
+
+    for byte in path.chars() {
+        // Set when a state ends on a character it doesn't consume itself
+        let mut reparse = true;
+
+        while reparse {
+            reparse = false;
+
+            match parse_state {
+                ParseState::Initial => {
+                    if byte != '/' {
+                        return Err(PathToRegexError::MissingLeadingForwardSlash);
+                    }
+
+                    pattern.push_static(byte);
                     parse_state = ParseState::Static;
                 }
-            }
-            ParseState::VarName(mut name) => {
-                if byte == '/' {
-                    // Validate 'name' as a Rust identifier
-                    if !ident_regex.is_match(&name) {
-                        return Err(PathToRegexError::InvalidIdentifier(name));
+                ParseState::Static => {
+                    if byte == ':' {
+                        parse_state = ParseState::ParamName("".to_string(), ParamKind::Segment);
+                    } else if byte == '*' {
+                        parse_state = ParseState::ParamName("".to_string(), ParamKind::Splat);
+                    } else {
+                        pattern.push_static(byte);
+                        parse_state = ParseState::Static;
+                    }
+                }
+                ParseState::ParamName(mut name, kind) => {
+                    if is_ident_char(byte) {
+                        name.push(byte);
+                        parse_state = ParseState::ParamName(name, kind);
+                    } else if byte == '(' {
+                        parse_state = ParseState::Constraint {
+                            name,
+                            kind,
+                            constraint: "".to_string(),
+                            depth: 0,
+                            escaped: false,
+                        };
+                    } else {
+                        parse_state = end_param(&mut pattern, name, kind, None, Some(byte))?;
+                        reparse = byte != '?';
+                    }
+                }
+                ParseState::Constraint {
+                    name,
+                    kind,
+                    mut constraint,
+                    depth,
+                    escaped,
+                } => {
+                    if byte == ')' && depth == 0 && !escaped {
+                        parse_state = ParseState::ConstraintEnd(name, kind, constraint);
+                    } else {
+                        let depth = match byte {
+                            '(' if !escaped => depth + 1,
+                            ')' if !escaped => depth - 1,
+                            _ => depth,
+                        };
+
+                        constraint.push(byte);
+                        parse_state = ParseState::Constraint {
+                            name,
+                            kind,
+                            constraint,
+                            depth,
+                            escaped: byte == '\\' && !escaped,
+                        };
+                    }
+                }
+                ParseState::ConstraintEnd(name, kind, constraint) => {
+                    parse_state =
+                        end_param(&mut pattern, name, kind, Some(constraint), Some(byte))?;
+                    reparse = byte != '?';
+                }
+                ParseState::SegmentEnd(name) => {
+                    // An optional param has to make up a whole segment
+                    if byte != '/' {
+                        return Err(PathToRegexError::InvalidOptionalParam(name));
                     }
 
-                    format_str += &format!("{}}}/", name);
-                    regex += &format!("(?P<{}>[^/]+)/", name);
                     parse_state = ParseState::Static;
-                } else {
-                    name.push(byte);
-                    parse_state = ParseState::VarName(name);
+                    reparse = true;
                 }
-            }
-        };
+            };
+        }
     }
 
-    if let ParseState::VarName(name) = parse_state {
-        regex += &format!("(?P<{}>[^/]+)", name);
-        format_str += &format!("{}}}", name);
+    match parse_state {
+        ParseState::ParamName(name, kind) => {
+            end_param(&mut pattern, name, kind, None, None)?;
+        }
+        ParseState::Constraint { name, .. } => {
+            return Err(PathToRegexError::UnterminatedConstraint(name));
+        }
+        ParseState::ConstraintEnd(name, kind, constraint) => {
+            end_param(&mut pattern, name, kind, Some(constraint), None)?;
+        }
+        _ => {}
     }
 
-    if regex.ends_with('/') {
+    if pattern.regex.ends_with('/') {
         return Err(PathToRegexError::InvalidTrailingSlash);
     }
 
-    regex += "$";
+    pattern.regex += "$";
 
-    Ok((regex, format_str))
+    Ok(pattern)
 }
 
 #[test]
 fn test_path_to_regex() {
-    let (regex, _) = path_to_regex("/p/:project_id/exams/:exam_id/submissions_expired").unwrap();
+    let PathPattern { regex, .. } =
+        path_to_regex("/p/:project_id/exams/:exam_id/submissions_expired").unwrap();
     assert_eq!(
         regex,
         r"^/p/(?P<project_id>[^/]+)/exams/(?P<exam_id>[^/]+)/submissions_expired$"
@@ -98,7 +359,7 @@ fn test_path_to_regex() {
 
 #[test]
 fn test_path_to_regex_no_path_params() {
-    let (regex, _) = path_to_regex("/p/exams/submissions_expired").unwrap();
+    let PathPattern { regex, .. } = path_to_regex("/p/exams/submissions_expired").unwrap();
     assert_eq!(regex, r"^/p/exams/submissions_expired$");
 }
 
@@ -116,10 +377,16 @@ fn test_path_to_regex_non_ascii_chars() {
 
 #[test]
 fn test_path_to_regex_invalid_ident() {
-    let regex = path_to_regex("/p/:project_id/exams/:exam*ID/submissions_expired");
+    let regex = path_to_regex("/p/:project_id/exams/:2exam_id/submissions_expired");
+    assert_eq!(
+        regex,
+        Err(PathToRegexError::InvalidIdentifier("2exam_id".to_string()))
+    );
+
+    let regex = path_to_regex("/p/:project_id/exams/:/submissions_expired");
     assert_eq!(
         regex,
-        Err(PathToRegexError::InvalidIdentifier("exam*ID".to_string()))
+        Err(PathToRegexError::InvalidIdentifier("".to_string()))
     );
 
     let regex = path_to_regex("/p/:project_id/exams/:_exam_id/submissions_expired");
@@ -129,13 +396,214 @@ fn test_path_to_regex_invalid_ident() {
     );
 }
 
+#[test]
+fn test_path_to_regex_splat() {
+    let PathPattern {
+        regex,
+        format_str,
+        params,
+        ..
+    } = path_to_regex("/files/:user_id/*rest").unwrap();
+    assert_eq!(regex, r"^/files/(?P<user_id>[^/]+)/(?P<rest>.*)$");
+    assert_eq!(format_str, "/files/{user_id}/{rest}");
+    assert_eq!(
+        params,
+        vec![
+            PathParam {
+                name: "user_id".to_string(),
+                kind: ParamKind::Segment,
+                optional: false,
+                constraint: None,
+            },
+            PathParam {
+                name: "rest".to_string(),
+                kind: ParamKind::Splat,
+                optional: false,
+                constraint: None,
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_path_to_regex_splat_not_at_end() {
+    let regex = path_to_regex("/files/*rest/edit");
+    assert_eq!(
+        regex,
+        Err(PathToRegexError::SplatNotAtEnd("rest".to_string()))
+    );
+}
+
+#[test]
+fn test_path_to_regex_optional() {
+    let PathPattern {
+        regex,
+        format_str,
+        params,
+        ..
+    } = path_to_regex("/users/:user_id/posts/:page?").unwrap();
+    assert_eq!(
+        regex,
+        r"^/users/(?P<user_id>[^/]+)/posts(?:/(?P<page>[^/]*))?$"
+    );
+    assert_eq!(format_str, "/users/{user_id}/posts{page}");
+    assert_eq!(
+        params[1],
+        PathParam {
+            name: "page".to_string(),
+            kind: ParamKind::Segment,
+            optional: true,
+            constraint: None,
+        }
+    );
+
+    let PathPattern { regex, .. } = path_to_regex("/users/:user_id?/edit").unwrap();
+    assert_eq!(regex, r"^/users(?:/(?P<user_id>[^/]*))?/edit$");
+}
+
+#[test]
+fn test_path_to_regex_optional_not_whole_segment() {
+    let regex = path_to_regex("/posts/:page?-latest");
+    assert_eq!(
+        regex,
+        Err(PathToRegexError::InvalidOptionalParam("page".to_string()))
+    );
+}
+
+#[test]
+fn test_path_to_regex_constraints() {
+    let PathPattern {
+        regex,
+        format_str,
+        params,
+        ..
+    } = path_to_regex(r"/users/:user_id(\d+)/:slug([a-z-]+)/:page(\d{1,3}|last)?").unwrap();
+    assert_eq!(
+        regex,
+        r"^/users/(?P<user_id>(?:\d+))/(?P<slug>(?:[a-z-]+))(?:/(?P<page>(?:\d{1,3}|last)))?$"
+    );
+    assert_eq!(format_str, "/users/{user_id}/{slug}{page}");
+    assert_eq!(params[0].constraint, Some(r"\d+".to_string()));
+
+    let PathPattern { regex, .. } = path_to_regex(r"/files/*path((?:[^/]+/)*[^/]+\.pdf)").unwrap();
+    assert_eq!(regex, r"^/files/(?P<path>(?:(?:[^/]+/)*[^/]+\.pdf))$");
+
+    let PathPattern { regex, .. } = path_to_regex(r"/p/:name(a\)b)").unwrap();
+    assert_eq!(regex, r"^/p/(?P<name>(?:a\)b))$");
+}
+
+#[test]
+fn test_path_to_regex_invalid_constraints() {
+    assert_eq!(
+        path_to_regex(r"/users/:user_id(\d+"),
+        Err(PathToRegexError::UnterminatedConstraint(
+            "user_id".to_string()
+        ))
+    );
+    assert_eq!(
+        path_to_regex(r"/users/:user_id([0-9)"),
+        Err(PathToRegexError::InvalidConstraint("[0-9".to_string()))
+    );
+    assert_eq!(
+        path_to_regex(r"/users/:user_id((\d+))"),
+        Err(PathToRegexError::ConstraintHasCaptureGroups(
+            r"(\d+)".to_string()
+        ))
+    );
+}
+
+#[test]
+fn test_path_to_regex_multiple_params_per_segment() {
+    let PathPattern {
+        regex, format_str, ..
+    } = path_to_regex("/posts/:id-:slug").unwrap();
+    assert_eq!(regex, r"^/posts/(?P<id>[^/]+?)-(?P<slug>[^/]+)$");
+    assert_eq!(format_str, "/posts/{id}-{slug}");
+
+    let PathPattern {
+        regex, format_str, ..
+    } = path_to_regex("/files/:name.:ext").unwrap();
+    assert_eq!(regex, r"^/files/(?P<name>[^/]+?)\.(?P<ext>[^/]+)$");
+    assert_eq!(format_str, "/files/{name}.{ext}");
+
+    let PathPattern {
+        regex, format_str, ..
+    } = path_to_regex("/v:version/api").unwrap();
+    assert_eq!(regex, r"^/v(?P<version>[^/]+)/api$");
+    assert_eq!(format_str, "/v{version}/api");
+
+    let PathPattern { regex, .. } = path_to_regex(r"/users/:user_id(\d+)x/posts").unwrap();
+    assert_eq!(regex, r"^/users/(?P<user_id>(?:\d+))x/posts$");
+
+    let PathPattern { regex, params, .. } = path_to_regex("/p/:project_id@:exam_id~draft").unwrap();
+    assert_eq!(
+        regex,
+        r"^/p/(?P<project_id>[^/]+?)@(?P<exam_id>[^/]+?)~draft$"
+    );
+    assert_eq!(params.len(), 2);
+}
+
+#[test]
+fn test_path_to_regex_splat_with_suffix() {
+    let regex = path_to_regex("/files/*rest.pdf");
+    assert_eq!(
+        regex,
+        Err(PathToRegexError::SplatNotAtEnd("rest".to_string()))
+    );
+}
+
+#[test]
+fn test_path_to_regex_escapes_static_text() {
+    let PathPattern {
+        regex, format_str, ..
+    } = path_to_regex("/v1.0/items").unwrap();
+    assert_eq!(regex, r"^/v1\.0/items$");
+    assert_eq!(format_str, "/v1.0/items");
+
+    let PathPattern {
+        regex, format_str, ..
+    } = path_to_regex(r"/a.b+c?d(e)f|g[h]i{j}k^l$m\n#o&p-q~r/:id").unwrap();
+    assert_eq!(
+        regex,
+        r"^/a\.b\+c\?d\(e\)f\|g\[h\]i\{j\}k\^l\$m\\n#o&p-q~r/(?P<id>[^/]+)$"
+    );
+    assert_eq!(format_str, r"/a.b+c?d(e)f|g[h]i{{j}}k^l$m\n#o&p-q~r/{id}");
+}
+
+#[test]
+fn test_path_to_regex_tokens() {
+    let PathPattern { tokens, .. } = path_to_regex("/v:version/files/:name.:ext").unwrap();
+    assert_eq!(
+        tokens,
+        vec![
+            PathToken::Literal("/v".to_string()),
+            PathToken::Param(0),
+            PathToken::Literal("/files/".to_string()),
+            PathToken::Param(1),
+            PathToken::Literal(".".to_string()),
+            PathToken::Param(2),
+        ]
+    );
+
+    let PathPattern { tokens, .. } = path_to_regex("/users/:user_id/posts/:page?").unwrap();
+    assert_eq!(
+        tokens,
+        vec![
+            PathToken::Literal("/users/".to_string()),
+            PathToken::Param(0),
+            PathToken::Literal("/posts".to_string()),
+            PathToken::Param(1),
+        ]
+    );
+}
+
 #[test]
 fn test_path_to_regex_invalid_ending() {
     let regex = path_to_regex("/p/:project_id/exams/:exam_id/submissions_expired/");
     assert_eq!(regex, Err(PathToRegexError::InvalidTrailingSlash));
 }
 
-fn get_string_attr(name: &str, attrs: &[syn::Attribute]) -> Option<String> {
+fn get_string_attr(name: &str, attrs: &[syn::Attribute]) -> Option<syn::LitStr> {
     for attr in attrs {
         let attr = attr.parse_meta();
 
@@ -143,7 +611,7 @@ fn get_string_attr(name: &str, attrs: &[syn::Attribute]) -> Option<String> {
             if list.ident == name {
                 for thing in &list.nested {
                     if let syn::NestedMeta::Literal(syn::Lit::Str(str_lit)) = thing {
-                        return Some(str_lit.value());
+                        return Some(str_lit.clone());
                     }
                 }
             }
@@ -153,113 +621,1019 @@ fn get_string_attr(name: &str, attrs: &[syn::Attribute]) -> Option<String> {
     None
 }
 
-fn has_flag_attr(name: &str, attrs: &[syn::Attribute]) -> bool {
+fn has_attr(name: &str, attrs: &[syn::Attribute]) -> bool {
     for attr in attrs {
         let attr = attr.parse_meta();
 
-        if let Ok(syn::Meta::Word(ref ident)) = attr {
-            if ident == name {
-                return true;
-            }
+        match attr {
+            Ok(syn::Meta::Word(ref ident)) if ident == name => return true,
+            Ok(syn::Meta::List(ref list)) if list.ident == name => return true,
+            _ => {}
         }
     }
 
     false
 }
 
-fn get_struct_fields(data: &syn::Data) -> Vec<syn::Field> {
-    match data {
-        syn::Data::Struct(data_struct) => match data_struct.fields {
-            syn::Fields::Named(ref named_fields) => named_fields.named.iter().cloned().collect(),
-            _ => panic!("Struct fields must be named"),
-        },
-        _ => panic!("AppPath derive is only supported for structs"),
+/// Reads the query string key of a `#[query_param]` field, which is the field
+/// name unless it's given with `#[query_param(rename = "key")]`
+fn get_query_param_key(field: &syn::Field) -> Result<Option<String>, syn::Error> {
+    let mut key = None;
+
+    for attr in &field.attrs {
+        match attr.parse_meta() {
+            Ok(syn::Meta::Word(ref ident)) if ident == "query_param" => {
+                key = Some(field.ident.as_ref().unwrap().to_string());
+            }
+            Ok(syn::Meta::List(ref list)) if list.ident == "query_param" => {
+                for option in &list.nested {
+                    match option {
+                        syn::NestedMeta::Meta(syn::Meta::NameValue(syn::MetaNameValue {
+                            ref ident,
+                            lit: syn::Lit::Str(ref rename),
+                            ..
+                        })) if ident == "rename" => {
+                            key = Some(rename.value());
+                        }
+                        _ => {
+                            return Err(syn::Error::new_spanned(
+                                option,
+                                "unknown query_param option, expected `rename = \"key\"`",
+                            ))
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
     }
+
+    if key.is_some() && has_attr("query", &field.attrs) {
+        return Err(syn::Error::new_spanned(
+            field.ident.as_ref().unwrap(),
+            "a field can't be both #[query] and #[query_param]",
+        ));
+    }
+
+    Ok(key)
 }
 
-fn field_is_option(field: &syn::Field) -> bool {
+/// How an `Option` query field treats a query string it can't parse
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum QueryMode {
+    /// Return a `QueryParseErr`, so `None` only ever means the query string is absent
+    Strict,
+    /// Quietly fall back to `None`
+    Lenient,
+}
+
+/// The options of `#[query(...)]` attributes, each `None` if it wasn't given
+#[derive(Clone, Default)]
+struct QueryOptions {
+    mode: Option<QueryMode>,
+    /// The `QueryCodec` from `#[query(codec = "Path")]`
+    codec: Option<syn::Path>,
+    /// The `QueryEncoding` variant from `#[query(encoding = "form")]` or
+    /// `#[query(encoding = "rfc3986")]`
+    encoding: Option<syn::Ident>,
+    /// `#[query(deny_unknown)]` on a path, which rejects query keys none of
+    /// its fields read
+    deny_unknown: Option<syn::Ident>,
+    /// `#[query(rest)]` on the map or `Vec` of pairs which collects the query
+    /// params none of the other fields read
+    rest: Option<syn::Ident>,
+    /// `#[query(canonical)]` on a path, which always writes its query string
+    /// the way `write_canonical_query` does
+    canonical: Option<syn::Ident>,
+}
+
+impl QueryOptions {
+    /// Fills in the options which weren't given from `defaults`, like the
+    /// options on a struct for one of its fields
+    fn or(self, defaults: &QueryOptions) -> QueryOptions {
+        QueryOptions {
+            mode: self.mode.or(defaults.mode),
+            codec: self.codec.or_else(|| defaults.codec.clone()),
+            encoding: self.encoding.or_else(|| defaults.encoding.clone()),
+            deny_unknown: self.deny_unknown.or_else(|| defaults.deny_unknown.clone()),
+            rest: self.rest,
+            canonical: self.canonical.or_else(|| defaults.canonical.clone()),
+        }
+    }
+
+    fn encoding(&self) -> proc_macro2::TokenStream {
+        match self.encoding {
+            Some(ref encoding) => quote!(rs_frame::QueryEncoding::#encoding),
+            None => quote!(rs_frame::QueryEncoding::Form),
+        }
+    }
+}
+
+/// Reads the options out of attributes like `#[query(strict)]`,
+/// `#[query(lenient)]`, `#[query(codec = "rs_frame::codec::RepeatedKeys")]`
+/// `#[query(encoding = "rfc3986")]`, `#[query(deny_unknown)]`, `#[query(rest)]`
+/// and `#[query(canonical)]`
+fn get_query_options(attrs: &[syn::Attribute]) -> Result<QueryOptions, syn::Error> {
+    let mut options = QueryOptions::default();
+
+    for attr in attrs {
+        if let Ok(syn::Meta::List(ref list)) = attr.parse_meta() {
+            if list.ident != "query" {
+                continue;
+            }
+
+            for option in &list.nested {
+                match option {
+                    syn::NestedMeta::Meta(syn::Meta::Word(ref ident)) if ident == "strict" => {
+                        options.mode = Some(QueryMode::Strict);
+                    }
+                    syn::NestedMeta::Meta(syn::Meta::Word(ref ident)) if ident == "lenient" => {
+                        options.mode = Some(QueryMode::Lenient);
+                    }
+                    syn::NestedMeta::Meta(syn::Meta::Word(ref ident))
+                        if ident == "deny_unknown" =>
+                    {
+                        options.deny_unknown = Some(ident.clone());
+                    }
+                    syn::NestedMeta::Meta(syn::Meta::Word(ref ident)) if ident == "rest" => {
+                        options.rest = Some(ident.clone());
+                    }
+                    syn::NestedMeta::Meta(syn::Meta::Word(ref ident)) if ident == "canonical" => {
+                        options.canonical = Some(ident.clone());
+                    }
+                    syn::NestedMeta::Meta(syn::Meta::NameValue(syn::MetaNameValue {
+                        ref ident,
+                        lit: syn::Lit::Str(ref codec),
+                        ..
+                    })) if ident == "codec" => {
+                        options.codec = Some(codec.parse().map_err(|_| {
+                            syn::Error::new(codec.span(), "expected the path to a QueryCodec type")
+                        })?);
+                    }
+                    syn::NestedMeta::Meta(syn::Meta::NameValue(syn::MetaNameValue {
+                        ref ident,
+                        lit: syn::Lit::Str(ref encoding),
+                        ..
+                    })) if ident == "encoding" => {
+                        let variant = match encoding.value().as_str() {
+                            "form" => "Form",
+                            "rfc3986" => "Rfc3986",
+                            _ => {
+                                return Err(syn::Error::new(
+                                    encoding.span(),
+                                    "unknown query encoding, expected `form` or `rfc3986`",
+                                ))
+                            }
+                        };
+
+                        options.encoding = Some(syn::Ident::new(variant, encoding.span()));
+                    }
+                    _ => {
+                        return Err(syn::Error::new_spanned(
+                            option,
+                            "unknown query option, expected `strict`, `lenient`, `deny_unknown`, `rest`, `canonical`, `codec = \"...\"` or `encoding = \"...\"`",
+                        ))
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(options)
+}
+
+fn get_struct_fields(data_struct: &syn::DataStruct) -> Result<Vec<syn::Field>, syn::Error> {
+    match data_struct.fields {
+        syn::Fields::Named(ref named_fields) => Ok(named_fields.named.iter().cloned().collect()),
+        syn::Fields::Unnamed(ref fields) => Err(syn::Error::new_spanned(
+            fields,
+            "AppPath derive requires a struct with named fields",
+        )),
+        syn::Fields::Unit => Err(syn::Error::new_spanned(
+            data_struct.struct_token,
+            "AppPath derive requires a struct with named fields, use `struct Name {}`",
+        )),
+    }
+}
+
+fn field_type_is(field: &syn::Field, type_name: &str) -> bool {
     match field.ty {
         syn::Type::Path(ref type_path) => type_path
             .path
             .segments
             .iter()
             .last()
-            .map(|segment| segment.ident == "Option")
+            .map(|segment| segment.ident == type_name)
             .unwrap_or(false),
         _ => false,
     }
 }
 
-#[proc_macro_derive(AppPath, attributes(path, query))]
+fn field_is_option(field: &syn::Field) -> bool {
+    field_type_is(field, "Option")
+}
+
+fn field_is_vec(field: &syn::Field) -> bool {
+    field_type_is(field, "Vec")
+}
+
+#[proc_macro_derive(AppPath, attributes(path, query, query_param))]
 pub fn app_path_derive(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
 
-    let struct_fields = get_struct_fields(&input.data);
+    match derive_app_path(input) {
+        Ok(out) => out.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// The generated code for one `#[path]` pattern and the fields that fill it,
+/// shared by structs and by each variant of an enum.
+struct PathImpl {
+    path_regex_str: String,
+    /// Binds the path fields as `field_<name>` references, for `display`
+    path_fields_pattern: proc_macro2::TokenStream,
+    /// Writes the path into `f`, followed by the query string
+    display: proc_macro2::TokenStream,
+    /// Binds the query fields as `field_<name>` references, for `query_writer`
+    query_fields_pattern: proc_macro2::TokenStream,
+    /// Writes the query string into `w`
+    query_writer: proc_macro2::TokenStream,
+    /// Writes the canonical query string into `w`
+    canonical_query_writer: proc_macro2::TokenStream,
+    /// Writes the query string into `w` for `Display`, keeping the first
+    /// value where query fields conflict
+    display_query_writer: proc_macro2::TokenStream,
+    /// Whether there's a `#[query]` field which isn't an `Option`
+    requires_query: bool,
+    /// Parses `app_path`, evaluating to a `Result<Self, PathParseErr>`
+    parser: proc_macro2::TokenStream,
+}
 
-    let (path_fields, query_fields): (Vec<_>, Vec<_>) = struct_fields
-        .into_iter()
-        .partition(|f| !has_flag_attr("query", &f.attrs));
+fn derive_app_path(input: DeriveInput) -> Result<proc_macro2::TokenStream, syn::Error> {
+    match input.data {
+        syn::Data::Struct(ref data_struct) => derive_struct(&input, data_struct),
+        syn::Data::Enum(ref data_enum) => derive_enum(&input, data_enum),
+        syn::Data::Union(ref data_union) => Err(syn::Error::new_spanned(
+            data_union.union_token,
+            "AppPath derive is only supported for structs and enums",
+        )),
+    }
+}
+
+fn derive_struct(
+    input: &DeriveInput,
+    data_struct: &syn::DataStruct,
+) -> Result<proc_macro2::TokenStream, syn::Error> {
+    let struct_fields = get_struct_fields(data_struct)?;
 
     let name = &input.ident;
-    let generics = input.generics;
-    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let path_lit = get_string_attr("path", &input.attrs).ok_or_else(|| {
+        syn::Error::new_spanned(
+            name,
+            "derive(AppPath) requires a #[path(\"/your/path/here\")] attribute on the struct",
+        )
+    })?;
+
+    let url_path = path_lit.value();
+
+    let PathImpl {
+        path_regex_str,
+        path_fields_pattern,
+        display,
+        query_fields_pattern,
+        query_writer,
+        canonical_query_writer,
+        display_query_writer,
+        requires_query,
+        parser,
+    } = derive_path_impl(
+        &quote!(#name),
+        &path_lit,
+        struct_fields,
+        &get_query_options(&input.attrs)?,
+    )?;
+
+    let app_path_impl = quote! {
+        impl #impl_generics rs_frame::AppPath for #name #ty_generics #where_clause {
+
+            fn path_pattern() -> String {
+                #path_regex_str.to_string()
+            }
 
-    let path_string = get_string_attr("path", &input.attrs);
+            fn path_templates() -> Vec<String> {
+                vec![#url_path.to_string()]
+            }
 
-    let url_path = path_string
-        .expect("derive(AppPath) requires a #[path(\"/your/path/here\")] attribute on the struct");
+            fn write_query(&self, w: &mut impl std::fmt::Write) -> Result<(), rs_frame::QueryWriteErr> {
+                let #query_fields_pattern = *self;
 
-    let (path_regex_str, format_str) =
-        path_to_regex(&url_path).expect("Could not convert path attribute to a valid regex");
+                #query_writer
+            }
 
-    // Validate path_regex and make sure struct and path have matching fields
-    let path_regex =
-        Regex::new(&path_regex_str).expect("path attribute was not compiled into a valid regex");
-
-    let regex_capture_names_set: HashSet<String> = path_regex
-        .capture_names()
-        .filter_map(|c_opt| c_opt.map(|c| c.to_string()))
-        .collect();
-    let field_names_set: HashSet<String> = path_fields
-        .clone()
+            fn write_canonical_query(&self, w: &mut impl std::fmt::Write) -> Result<(), rs_frame::QueryWriteErr> {
+                let #query_fields_pattern = *self;
+
+                #canonical_query_writer
+            }
+
+            fn write_display_query(&self, w: &mut impl std::fmt::Write) -> Result<(), rs_frame::QueryWriteErr> {
+                let #query_fields_pattern = *self;
+
+                #display_query_writer
+            }
+
+            fn requires_query(&self) -> bool {
+                #requires_query
+            }
+        }
+
+        impl #impl_generics std::fmt::Display for #name #ty_generics #where_clause {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                let #path_fields_pattern = *self;
+
+                #display
+            }
+        }
+
+        impl #impl_generics std::str::FromStr for #name #ty_generics #where_clause {
+            type Err = rs_frame::PathParseErr;
+
+            fn from_str(app_path: &str) -> Result<Self, Self::Err> {
+                use rs_frame::PathParseErr;
+
+                #parser
+            }
+        }
+    };
+
+    Ok(wrap_impl(name, app_path_impl))
+}
+
+fn derive_enum(
+    input: &DeriveInput,
+    data_enum: &syn::DataEnum,
+) -> Result<proc_macro2::TokenStream, syn::Error> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    if data_enum.variants.is_empty() {
+        return Err(syn::Error::new_spanned(
+            name,
+            "AppPath derive requires an enum with at least one variant",
+        ));
+    }
+
+    let enum_query_options = get_query_options(&input.attrs)?;
+
+    let mut path_patterns = vec![];
+    let mut path_templates = vec![];
+    let mut variant_path_patterns = vec![];
+    let mut display_arms = vec![];
+    let mut query_arms = vec![];
+    let mut canonical_query_arms = vec![];
+    let mut display_query_arms = vec![];
+    let mut requires_query_arms = vec![];
+    let mut parsers = vec![];
+
+    for variant in &data_enum.variants {
+        let variant_ident = &variant.ident;
+        let path_lit = get_string_attr("path", &variant.attrs);
+
+        // A single unnamed field wraps another AppPath type, which brings its own path
+        if let syn::Fields::Unnamed(ref fields) = variant.fields {
+            if let Some(path_lit) = path_lit {
+                return Err(syn::Error::new(
+                    path_lit.span(),
+                    "a variant wrapping another AppPath type uses that type's path, remove the #[path] attribute",
+                ));
+            }
+
+            if fields.unnamed.len() != 1 {
+                return Err(syn::Error::new_spanned(
+                    fields,
+                    "AppPath derive requires variants with named fields or a single wrapped AppPath type",
+                ));
+            }
+
+            let wrapped_ty = &fields.unnamed[0].ty;
+
+            path_patterns.push(quote! {
+                <#wrapped_ty as rs_frame::AppPath>::path_pattern()
+            });
+            path_templates.push(quote! {
+                <#wrapped_ty as rs_frame::AppPath>::path_templates()
+            });
+            variant_path_patterns.push(quote! {
+                <#wrapped_ty as rs_frame::AppPath>::path_patterns()
+            });
+            display_arms.push(quote! {
+                #name::#variant_ident(ref wrapped) => std::fmt::Display::fmt(wrapped, f)
+            });
+            query_arms.push(quote! {
+                #name::#variant_ident(ref wrapped) => rs_frame::AppPath::write_query(wrapped, w)
+            });
+            canonical_query_arms.push(quote! {
+                #name::#variant_ident(ref wrapped) => rs_frame::AppPath::write_canonical_query(wrapped, w)
+            });
+            display_query_arms.push(quote! {
+                #name::#variant_ident(ref wrapped) => rs_frame::AppPath::write_display_query(wrapped, w)
+            });
+            requires_query_arms.push(quote! {
+                #name::#variant_ident(ref wrapped) => rs_frame::AppPath::requires_query(wrapped)
+            });
+            parsers.push(quote! {
+                std::str::FromStr::from_str(app_path).map(#name::#variant_ident)
+            });
+
+            continue;
+        }
+
+        let path_lit = path_lit.ok_or_else(|| {
+            syn::Error::new_spanned(
+                variant_ident,
+                "derive(AppPath) requires a #[path(\"/your/path/here\")] attribute on each variant, or a single wrapped AppPath type",
+            )
+        })?;
+
+        let variant_fields = match variant.fields {
+            syn::Fields::Named(ref named_fields) => named_fields.named.iter().cloned().collect(),
+            _ => vec![],
+        };
+
+        let PathImpl {
+            path_regex_str,
+            path_fields_pattern,
+            display,
+            query_fields_pattern,
+            query_writer,
+            canonical_query_writer,
+            display_query_writer,
+            requires_query,
+            parser,
+        } = derive_path_impl(
+            &quote!(#name::#variant_ident),
+            &path_lit,
+            variant_fields,
+            &get_query_options(&variant.attrs)?.or(&enum_query_options),
+        )?;
+
+        let url_path = path_lit.value();
+
+        path_patterns.push(quote! {
+            #path_regex_str.to_string()
+        });
+        path_templates.push(quote! {
+            vec![#url_path.to_string()]
+        });
+        variant_path_patterns.push(quote! {
+            vec![#path_regex_str.to_string()]
+        });
+        display_arms.push(quote! {
+            #path_fields_pattern => { #display }
+        });
+        query_arms.push(quote! {
+            #query_fields_pattern => { #query_writer }
+        });
+        canonical_query_arms.push(quote! {
+            #query_fields_pattern => { #canonical_query_writer }
+        });
+        display_query_arms.push(quote! {
+            #query_fields_pattern => { #display_query_writer }
+        });
+        requires_query_arms.push(quote! {
+            #name::#variant_ident { .. } => #requires_query
+        });
+        parsers.push(quote! {
+            (|| -> Result<Self, PathParseErr> { #parser })()
+        });
+    }
+
+    let app_path_impl = quote! {
+        impl #impl_generics rs_frame::AppPath for #name #ty_generics #where_clause {
+
+            /// The patterns of each variant, in the order they're tried, joined
+            /// with `|`. Variants can share param names, so the groups aren't named.
+            fn path_pattern() -> String {
+                let patterns: Vec<String> = vec![#(#path_patterns),*];
+                patterns
+                    .iter()
+                    .map(|pattern| rs_frame::unname_groups(pattern))
+                    .collect::<Vec<_>>()
+                    .join("|")
+            }
+
+            fn path_templates() -> Vec<String> {
+                let templates: Vec<Vec<String>> = vec![#(#path_templates),*];
+                templates.concat()
+            }
+
+            fn path_patterns() -> Vec<String> {
+                let patterns: Vec<Vec<String>> = vec![#(#variant_path_patterns),*];
+                patterns.concat()
+            }
+
+            fn write_query(&self, w: &mut impl std::fmt::Write) -> Result<(), rs_frame::QueryWriteErr> {
+                match *self {
+                    #(#query_arms),*
+                }
+            }
+
+            fn write_canonical_query(&self, w: &mut impl std::fmt::Write) -> Result<(), rs_frame::QueryWriteErr> {
+                match *self {
+                    #(#canonical_query_arms),*
+                }
+            }
+
+            fn write_display_query(&self, w: &mut impl std::fmt::Write) -> Result<(), rs_frame::QueryWriteErr> {
+                match *self {
+                    #(#display_query_arms),*
+                }
+            }
+
+            fn requires_query(&self) -> bool {
+                match *self {
+                    #(#requires_query_arms),*
+                }
+            }
+        }
+
+        impl #impl_generics std::fmt::Display for #name #ty_generics #where_clause {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                match *self {
+                    #(#display_arms),*
+                }
+            }
+        }
+
+        impl #impl_generics std::str::FromStr for #name #ty_generics #where_clause {
+            type Err = rs_frame::PathParseErr;
+
+            /// Tries each variant in declaration order and returns the first that parses.
+            /// If none do, the error from the first variant whose path matched is
+            /// returned, or `NoMatches` if no path matched at all.
+            fn from_str(app_path: &str) -> Result<Self, Self::Err> {
+                use rs_frame::PathParseErr;
+
+                let mut first_err = None;
+
+                #(
+                    match #parsers {
+                        Ok(route) => return Ok(route),
+                        Err(PathParseErr::NoMatches) => {}
+                        Err(e) => {
+                            first_err = first_err.or(Some(e));
+                        }
+                    }
+                )*
+
+                Err(first_err.unwrap_or(PathParseErr::NoMatches))
+            }
+        }
+    };
+
+    Ok(wrap_impl(name, app_path_impl))
+}
+
+fn wrap_impl(
+    name: &syn::Ident,
+    app_path_impl: proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    let impl_wrapper = syn::Ident::new(
+        &format!("_IMPL_APPPATH_FOR_{}", name.to_string()),
+        proc_macro2::Span::call_site(),
+    );
+
+    quote! {
+        const #impl_wrapper: () = {
+            extern crate rs_frame;
+            #app_path_impl
+        };
+    }
+}
+
+/// Generates the parsing and formatting code for `path_lit` and `fields`, where
+/// `constructor` is the path used to build and destructure the value, like
+/// `Name` for a struct or `Name::Variant` for an enum variant.
+fn derive_path_impl(
+    constructor: &proc_macro2::TokenStream,
+    path_lit: &syn::LitStr,
+    fields: Vec<syn::Field>,
+    default_query_options: &QueryOptions,
+) -> Result<PathImpl, syn::Error> {
+    let (path_fields, query_fields): (Vec<_>, Vec<_>) = fields
         .into_iter()
-        .map(|f| f.ident.unwrap().to_string())
-        .collect();
+        .partition(|f| !has_attr("query", &f.attrs) && !has_attr("query_param", &f.attrs));
+
+    let url_path = path_lit.value();
 
-    if regex_capture_names_set != field_names_set {
-        let missing_from_path = field_names_set.difference(&regex_capture_names_set);
-        let missing_from_struct = regex_capture_names_set.difference(&field_names_set);
+    let PathPattern {
+        regex: path_regex_str,
+        format_str,
+        params: path_params,
+        tokens,
+    } = path_to_regex(&url_path).map_err(|e| syn::Error::new(path_lit.span(), e))?;
 
-        let error_msg = format!("\nFields in struct missing from path pattern: {:?}\nFields in path missing from struct: {:?}", missing_from_path, missing_from_struct);
-        panic!(error_msg);
+    // Validate path_regex and make sure struct and path have matching fields
+    Regex::new(&path_regex_str).map_err(|e| {
+        syn::Error::new(
+            path_lit.span(),
+            format!("path attribute was not compiled into a valid regex: {}", e),
+        )
+    })?;
+
+    for f in &path_fields {
+        let f_ident = f.ident.as_ref().unwrap();
+
+        if !path_params.iter().any(|p| f_ident == &p.name) {
+            return Err(syn::Error::new_spanned(
+                f_ident,
+                format!(
+                    "field `{}` is missing from the path pattern, add `:{}` to the path or mark it with #[query]",
+                    f_ident, f_ident
+                ),
+            ));
+        }
     }
 
-    let path_field_assignments = path_fields.clone().into_iter().map(|f| {
-        let f_ident = f.ident.unwrap();
-        let f_ident_str = f_ident.to_string();
+    for param in &path_params {
+        if !path_fields
+            .iter()
+            .any(|f| f.ident.as_ref().unwrap() == &param.name)
+        {
+            return Err(syn::Error::new(
+                path_lit.span(),
+                format!(
+                    "path parameter `{}` is missing from the struct fields",
+                    param.name
+                ),
+            ));
+        }
+    }
+
+    let param_for_field = |f: &syn::Field| {
+        let f_ident_str = f.ident.as_ref().unwrap().to_string();
+
+        path_params
+            .iter()
+            .find(|p| p.name == f_ident_str)
+            .expect("path param missing for field")
+    };
+
+    for f in &path_fields {
+        let param = param_for_field(f);
+
+        if param.optional && !field_is_option(f) {
+            return Err(syn::Error::new_spanned(
+                &f.ty,
+                format!(
+                    "optional path parameter `{}` must have an Option field type",
+                    param.name
+                ),
+            ));
+        }
+
+        if !param.optional && field_is_option(f) {
+            return Err(syn::Error::new_spanned(
+                &f.ty,
+                format!(
+                    "path field `{}` is an Option, mark it as optional in the path with `:{}?`",
+                    param.name, param.name
+                ),
+            ));
+        }
+    }
+
+    let is_splat_vec =
+        |f: &syn::Field| field_is_vec(f) && param_for_field(f).kind == ParamKind::Splat;
+
+    let param_var =
+        |name: &str| syn::Ident::new(&format!("param_{}", name), proc_macro2::Span::call_site());
+
+    // Constraints need the regex engine, and so do patterns where the end of a
+    // param can't be found by looking ahead for the static text that follows it
+    let needs_regex = path_params.iter().any(|p| p.constraint.is_some())
+        || tokens
+            .windows(2)
+            .any(|pair| matches!(pair, [PathToken::Param(_), PathToken::Param(_)]))
+        || tokens.iter().rev().skip(1).any(|token| match token {
+            PathToken::Param(i) => path_params[*i].optional,
+            _ => false,
+        });
+
+    let path_matcher = if needs_regex {
+        let capture_bindings = path_params.iter().map(|p| {
+            let var = param_var(&p.name);
+            let name = &p.name;
+
+            if p.optional {
+                quote! {
+                    let #var = captures.name(#name).map(|capture| capture.as_str());
+                }
+            } else {
+                quote! {
+                    let #var = &captures[#name];
+                }
+            }
+        });
 
         quote! {
-            #f_ident: captures[#f_ident_str].parse().map_err(|e| {
-                PathParseErr::ParamParseErr(std::string::ToString::to_string(&e))
-            })?
+            rs_frame::lazy_static! {
+                static ref PATH_REGEX: rs_frame::Regex = rs_frame::Regex::new(#path_regex_str).expect("Failed to compile regex");
+            }
+
+            let captures = (*PATH_REGEX).captures(just_path).ok_or(PathParseErr::NoMatches)?;
+
+            #(#capture_bindings)*
         }
-    });
+    } else {
+        let match_steps = tokens.iter().enumerate().map(|(i, token)| match token {
+            PathToken::Literal(literal) => quote! {
+                let path = rs_frame::match_literal(path, #literal).ok_or(PathParseErr::NoMatches)?;
+            },
+            PathToken::Param(param_index) => {
+                let param = &path_params[*param_index];
+                let var = param_var(&param.name);
+
+                if param.kind == ParamKind::Splat {
+                    quote! {
+                        let (#var, path) = rs_frame::match_splat(path);
+                    }
+                } else if param.optional {
+                    quote! {
+                        let (#var, path) = rs_frame::match_optional_param(path).ok_or(PathParseErr::NoMatches)?;
+                    }
+                } else {
+                    // The static text up to the end of the segment tells us where the param stops
+                    let (until, ends_segment) = match tokens.get(i + 1) {
+                        Some(PathToken::Literal(literal)) => (
+                            literal.split('/').next().unwrap(),
+                            literal.contains('/') || i + 2 == tokens.len(),
+                        ),
+                        _ => ("", true),
+                    };
+
+                    // When nothing but that text is left in the segment, the
+                    // param runs up to it at the end of the segment, the same
+                    // as the regex would backtrack to
+                    if ends_segment && !until.is_empty() {
+                        quote! {
+                            let (#var, path) = rs_frame::match_param_before(path, #until).ok_or(PathParseErr::NoMatches)?;
+                        }
+                    } else {
+                        quote! {
+                            let (#var, path) = rs_frame::match_param(path, #until).ok_or(PathParseErr::NoMatches)?;
+                        }
+                    }
+                }
+            }
+        });
+
+        quote! {
+            let path = just_path;
+
+            #(#match_steps)*
+
+            if !path.is_empty() {
+                return Err(PathParseErr::NoMatches);
+            }
+        }
+    };
 
-    let query_field_assignments = query_fields.clone().into_iter().map(|f| {
-        let is_option = field_is_option(&f);
+    let path_field_assignments = path_fields.clone().into_iter().map(|f| {
+        let splat_vec = is_splat_vec(&f);
+        let optional = param_for_field(&f).optional;
         let f_ident = f.ident.unwrap();
+        let f_name = f_ident.to_string();
+        let var = param_var(&f_name);
 
-        if is_option {
+        if splat_vec {
+            quote! {
+                #f_ident: rs_frame::splat_segments(#var)
+                    .map(|segment| rs_frame::parse_path_param(segment, #f_name, #url_path))
+                    .collect::<Result<_, _>>()?
+            }
+        } else if optional {
             quote! {
-                #f_ident: query_string.and_then(|q| qs::from_str(q).ok())
+                #f_ident: match #var {
+                    Some(param) => Some(rs_frame::parse_path_param(param, #f_name, #url_path)?),
+                    None => None,
+                }
             }
         } else {
             quote! {
-                #f_ident: qs::from_str(query_string.ok_or(PathParseErr::NoQueryString)?).map_err(|e| PathParseErr::QueryParseErr(e.description().to_string()))?
+                #f_ident: rs_frame::parse_path_param(#var, #f_name, #url_path)?
             }
         }
     });
 
+    let field_var = |f: &syn::Field| {
+        syn::Ident::new(
+            &format!("field_{}", f.ident.as_ref().unwrap()),
+            proc_macro2::Span::call_site(),
+        )
+    };
+
+    let mut query_field_assignments = vec![];
+    let mut query_field_write_statements = vec![];
+    let mut query_param_keys = vec![];
+    // What each query field reads, for `deny_unknown` and `rest`
+    let mut known_query_keys = vec![];
+    let mut rest_field = None;
+    let mut requires_query = false;
+
+    if let Some(ref rest) = default_query_options.rest {
+        return Err(syn::Error::new_spanned(
+            rest,
+            "`rest` goes on the map or `Vec` field which collects the unknown query params",
+        ));
+    }
+
+    for f in &query_fields {
+        let f_ident = f.ident.as_ref().unwrap();
+        let f_name = f_ident.to_string();
+        let var = field_var(f);
+        let is_option = field_is_option(f);
+
+        if let Some(key) = get_query_param_key(f)? {
+            if query_param_keys.contains(&key) {
+                return Err(syn::Error::new_spanned(
+                    f_ident,
+                    format!(
+                        "query key `{}` is already used by another #[query_param]",
+                        key
+                    ),
+                ));
+            }
+
+            query_param_keys.push(key.clone());
+            known_query_keys.push(quote!(Some(&[#key][..])));
+
+            let encoding = default_query_options.encoding();
+
+            if is_option {
+                query_field_assignments.push(quote! {
+                    #f_ident: rs_frame::parse_query_param(query_string, #key, #encoding, #f_name, #url_path)?
+                });
+                query_field_write_statements.push(quote! {
+                    if let Some(ref value) = *#var {
+                        query.write_param(#key, value, #encoding)?;
+                    }
+                });
+            } else {
+                query_field_assignments.push(quote! {
+                    #f_ident: rs_frame::parse_required_query_param(query_string, #key, #encoding, #f_name, #url_path)?
+                });
+                query_field_write_statements.push(quote! {
+                    query.write_param(#key, #var, #encoding)?;
+                });
+            }
+
+            continue;
+        }
+
+        let query_options = get_query_options(&f.attrs)?;
+
+        if let Some(ref path_option) = query_options
+            .deny_unknown
+            .as_ref()
+            .or(query_options.canonical.as_ref())
+        {
+            return Err(syn::Error::new_spanned(
+                path_option,
+                format!(
+                    "`{}` applies to the whole query string, put it on the struct or variant",
+                    path_option
+                ),
+            ));
+        }
+
+        if let Some(ref rest) = query_options.rest {
+            if rest_field.is_some() {
+                return Err(syn::Error::new_spanned(
+                    rest,
+                    "only one field can collect the rest of the query params",
+                ));
+            }
+
+            if let Some(ref deny_unknown) = default_query_options.deny_unknown {
+                return Err(syn::Error::new_spanned(
+                    deny_unknown,
+                    "`deny_unknown` can't be used with a `rest` field, which keeps the unknown query params",
+                ));
+            }
+
+            if is_option {
+                return Err(syn::Error::new_spanned(
+                    &f.ty,
+                    format!(
+                        "the `rest` field `{}` can't be an Option, it's left empty when there's nothing to collect",
+                        f_ident
+                    ),
+                ));
+            }
+
+            let encoding = query_options.or(default_query_options).encoding();
+
+            // Written all at once, so a key the field holds more than once
+            // isn't a conflict with itself
+            query_field_write_statements.push(quote! {
+                query.write_params(#var.iter().map(|(key, value)| (key, value)), #encoding)?;
+            });
+            rest_field = Some((f_ident, field_is_vec(f), encoding));
+
+            continue;
+        }
+
+        let f_ty = &f.ty;
+        known_query_keys.push(quote!(rs_frame::query_keys::<#f_ty>()));
+
+        if !is_option && query_options.mode == Some(QueryMode::Lenient) {
+            return Err(syn::Error::new_spanned(
+                &f.ty,
+                format!("query field `{}` must be an Option to be lenient", f_ident),
+            ));
+        }
+
+        let query_options = query_options.or(default_query_options);
+        let codec = match query_options.codec {
+            Some(ref codec) => quote!(#codec),
+            None => quote!(rs_frame::codec::SerdeQs),
+        };
+        let encoding = query_options.encoding();
+
+        let assignment = if !is_option {
+            requires_query = true;
+
+            quote! {
+                #f_ident: rs_frame::parse_query::<#codec, _>(
+                    query_string.ok_or(PathParseErr::NoQueryString)?,
+                    #encoding,
+                    #f_name,
+                    #url_path,
+                )?
+            }
+        } else if query_options.mode == Some(QueryMode::Strict) {
+            quote! {
+                #f_ident: match query_string {
+                    Some(q) if rs_frame::query_has_keys(q, rs_frame::query_keys::<#f_ty>(), #encoding) => {
+                        Some(rs_frame::parse_query::<#codec, _>(q, #encoding, #f_name, #url_path)?)
+                    }
+                    _ => None,
+                }
+            }
+        } else {
+            quote! {
+                #f_ident: query_string.and_then(|q| <#codec as rs_frame::QueryCodec>::decode(q, #encoding).ok())
+            }
+        };
+
+        query_field_assignments.push(assignment);
+
+        if is_option {
+            query_field_write_statements.push(quote! {
+                if let Some(ref q) = *#var {
+                    query.write_part::<#codec, _>(q, #encoding)?;
+                }
+            });
+        } else {
+            query_field_write_statements.push(quote! {
+                query.write_part::<#codec, _>(#var, #encoding)?;
+            });
+        }
+    }
+
+    let known_query_keys_binding = quote! {
+        let known_query_keys: &[Option<&'static [&'static str]>] = &[#(#known_query_keys),*];
+    };
+
+    let mut unknown_query_check = quote! {};
+
+    if let Some((f_ident, is_vec, encoding)) = rest_field {
+        // A map keeps the last value it's given for a key, so it's handed the
+        // pairs backwards to keep the first one instead
+        let pairs = if is_vec {
+            quote!(rs_frame::unknown_query_pairs(query_string, known_query_keys, #encoding).into_iter())
+        } else {
+            quote!(rs_frame::unknown_query_pairs(query_string, known_query_keys, #encoding).into_iter().rev())
+        };
+
+        query_field_assignments.push(quote! {
+            #f_ident: #pairs.collect()
+        });
+        unknown_query_check = known_query_keys_binding;
+    } else if default_query_options.deny_unknown.is_some() {
+        let encoding = default_query_options.encoding();
+
+        unknown_query_check = quote! {
+            #known_query_keys_binding
+
+            rs_frame::deny_unknown_query_keys(query_string, known_query_keys, #encoding)?;
+        };
+    }
+
     let path_field_parsers = quote! {
         #(
             #path_field_assignments
@@ -272,153 +1646,161 @@ pub fn app_path_derive(input: TokenStream) -> TokenStream {
         ),*
     };
 
-    let format_args = path_fields.clone().into_iter().map(|f| {
-        let f_ident = f.ident.unwrap();
+    let fields_pattern = |fields: &[syn::Field]| {
+        let bindings = fields.iter().map(|f| {
+            let f_ident = f.ident.as_ref().unwrap();
+            let var = field_var(f);
+
+            quote! {
+                #f_ident: ref #var
+            }
+        });
 
         quote! {
-            #f_ident = self.#f_ident
+            #constructor { #(#bindings,)* .. }
         }
-    });
-
-    let format_args = quote! {
-        #(
-            #format_args
-        ),*
     };
 
-    let query_field_to_string_statements = query_fields.into_iter().map(|f| {
-        let is_option = field_is_option(&f);
-        let f_ident = f.ident.unwrap();
+    let format_args = path_fields.iter().map(|f| {
+        let param = param_for_field(f);
+        let f_ident = f.ident.as_ref().unwrap();
+        let var = field_var(f);
 
-        if is_option {
+        if is_splat_vec(f) {
+            quote! {
+                #f_ident = rs_frame::EncodeSplatSegments(#var)
+            }
+        } else if param.kind == ParamKind::Splat {
             quote! {
-                self.#f_ident.as_ref().and_then(|q| qs::to_string(&q).ok())
+                #f_ident = rs_frame::EncodeSplatParam(#var)
+            }
+        } else if param.optional {
+            quote! {
+                #f_ident = rs_frame::EncodeOptionalParam(#var.as_ref())
             }
         } else {
             quote! {
-                qs::to_string(&self.#f_ident).ok()
+                #f_ident = rs_frame::EncodePathParam(#var)
             }
         }
     });
 
-    let encoded_query_fields = quote! {
+    let format_args = quote! {
         #(
-            #query_field_to_string_statements
+            #format_args
         ),*
     };
 
-    let struct_constructor = match (
-        path_field_parsers.is_empty(),
-        query_field_parsers.is_empty(),
-    ) {
-        (true, true) => quote! {
-            #name {}
-        },
-        (true, false) => quote! {
-            #name {
-                #query_field_parsers
-            }
-        },
-        (false, true) => quote! {
-            #name {
-                #path_field_parsers
-            }
-        },
-        (false, false) => quote! {
-            #name {
-                #path_field_parsers,
-                #query_field_parsers
-            }
-        },
-    };
+    // Paths which can't parse without a query string get a `?` even when
+    // it's empty
+    let display_query = if requires_query {
+        quote! {
+            std::fmt::Write::write_str(f, "?")?;
 
-    let app_path_impl = quote! {
-        impl #impl_generics rs_frame::AppPath for #name #ty_generics #where_clause {
+            rs_frame::AppPath::write_display_query(self, f).map_err(|_| std::fmt::Error)
+        }
+    } else {
+        quote! {
+            rs_frame::AppPath::write_display_query(self, &mut rs_frame::LazyPrefix::new(f, "?"))
+                .map_err(|_| std::fmt::Error)
+        }
+    };
 
-            fn path_pattern() -> String {
-                #path_regex_str.to_string()
-            }
+    let display = quote! {
+        write!(
+            f,
+            #format_str,
+            #format_args
+        )?;
 
-            fn query_string(&self) -> Option<String> {
-                use rs_frame::serde_qs as qs;
+        #display_query
+    };
 
-                // TODO - Remove duplicates because
-                //        there could be multiple fields with
-                //        a #[query] attribute that have common fields
+    // Query fields share one key space, which takes some bookkeeping
+    // that a lone field doesn't need
+    let new_query_writer = if query_field_write_statements.len() > 1 {
+        quote!(rs_frame::QueryWriter::merging(w))
+    } else {
+        quote!(rs_frame::QueryWriter::new(w))
+    };
 
-                // TODO - can this be done with an on-stack array?
-                let encoded_queries: Vec<Option<String>> = vec![#encoded_query_fields];
-                let filtered: Vec<_> = encoded_queries.into_iter().filter_map(std::convert::identity).collect();
+    let write_statements = &query_field_write_statements;
+    let write_query_with = |new_query_writer: proc_macro2::TokenStream| {
+        if write_statements.is_empty() {
+            quote! {
+                let _ = w;
 
-                if !filtered.is_empty() {
-                    Some(filtered.join("&"))
-                } else {
-                    None
-                }
+                Ok(())
             }
-        }
+        } else {
+            quote! {
+                let mut query = #new_query_writer;
 
-        impl #impl_generics std::fmt::Display for #name #ty_generics #where_clause {
-            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-                if let Some(query) = self.query_string() {
-                    let path = format!(
-                        #format_str,
-                        #format_args
-                    );
+                #(#write_statements)*
 
-                    write!(f, "{}?{}", path, query)
-                } else {
-                    write!(
-                        f,
-                        #format_str,
-                        #format_args
-                    )
-                }
+                query.finish()
             }
         }
+    };
 
-        impl #impl_generics std::str::FromStr for #name #ty_generics #where_clause {
-            type Err = rs_frame::PathParseErr;
-
-            fn from_str(app_path: &str) -> Result<Self, Self::Err> {
-                use rs_frame::serde_qs as qs;
-                use rs_frame::PathParseErr;
-
-                rs_frame::lazy_static! {
-                    static ref PATH_REGEX: rs_frame::Regex = rs_frame::Regex::new(#path_regex_str).expect("Failed to compile regex");
-                }
-
-                let question_pos = app_path.find('?');
-                let just_path = &app_path[..(question_pos.unwrap_or_else(|| app_path.len()))];
+    let canonical_query_writer = write_query_with(quote!(rs_frame::QueryWriter::canonical(w)));
+    let (query_writer, display_query_writer) = if default_query_options.canonical.is_some() {
+        (
+            canonical_query_writer.clone(),
+            write_query_with(quote!(rs_frame::QueryWriter::canonical(w).keep_first())),
+        )
+    } else {
+        (
+            write_query_with(new_query_writer.clone()),
+            write_query_with(quote!(#new_query_writer.keep_first())),
+        )
+    };
 
-                let captures = (*PATH_REGEX).captures(just_path).ok_or(PathParseErr::NoMatches)?;
+    let constructor_fields = match (
+        path_field_parsers.is_empty(),
+        query_field_parsers.is_empty(),
+    ) {
+        (true, true) => quote! {},
+        (true, false) => query_field_parsers,
+        (false, true) => path_field_parsers,
+        (false, false) => quote! {
+            #path_field_parsers,
+            #query_field_parsers
+        },
+    };
 
-                let query_string = question_pos.map(|question_pos| {
-                    let mut query_string = &app_path[question_pos..];
+    let parser = quote! {
+        let question_pos = app_path.find('?');
+        let just_path = &app_path[..(question_pos.unwrap_or_else(|| app_path.len()))];
 
-                    if query_string.starts_with('?') {
-                        query_string = &query_string[1..];
-                    }
+        #path_matcher
 
-                    query_string
-                });
+        let query_string = question_pos.map(|question_pos| {
+            let mut query_string = &app_path[question_pos..];
 
-                Ok(#struct_constructor)
+            if query_string.starts_with('?') {
+                query_string = &query_string[1..];
             }
-        }
-    };
 
-    let impl_wrapper = syn::Ident::new(
-        &format!("_IMPL_APPPATH_FOR_{}", name.to_string()),
-        proc_macro2::Span::call_site(),
-    );
+            query_string
+        });
 
-    let out = quote! {
-        const #impl_wrapper: () = {
-            extern crate rs_frame;
-            #app_path_impl
-        };
+        #unknown_query_check
+
+        Ok(#constructor {
+            #constructor_fields
+        })
     };
 
-    out.into()
+    Ok(PathImpl {
+        path_regex_str,
+        path_fields_pattern: fields_pattern(&path_fields),
+        display,
+        query_fields_pattern: fields_pattern(&query_fields),
+        query_writer,
+        canonical_query_writer,
+        display_query_writer,
+        requires_query,
+        parser,
+    })
 }
@@ -4,6 +4,7 @@ extern crate criterion;
 use rs_frame::AppPath;
 use serde::{Serialize, Deserialize};
 use criterion::Criterion;
+use regex::Regex;
 
 // Trivial case
 #[derive(AppPath, Debug, PartialEq)]
@@ -29,6 +30,29 @@ fn simple_benchmark(c: &mut Criterion) {
     }));
 }
 
+// What the simple case costs going through the regex, as `from_str` used to
+fn simple_regex_benchmark(c: &mut Criterion) {
+    let regex = Regex::new(&UserDetailPath::path_pattern()).unwrap();
+
+    c.bench_function("UserDetailPath regex", move |b| b.iter(|| {
+        let captures = regex.captures("/users/642151").unwrap();
+        let _user_id: u64 = captures["user_id"].parse().unwrap();
+    }));
+}
+
+// Constrained case, which still goes through a regex
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/users/:user_id(\\d+)")]
+struct UserDetailConstrainedPath {
+    user_id: u64,
+}
+
+fn constrained_benchmark(c: &mut Criterion) {
+    c.bench_function("UserDetailConstrainedPath", |b| b.iter(|| {
+        let _path: UserDetailConstrainedPath = "/users/642151".parse().unwrap();
+    }));
+}
+
 // Nested case
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Building {
@@ -93,5 +117,5 @@ fn vec_benchmark(c: &mut Criterion) {
     }));
 }
 
-criterion_group!(benches, trivial_benchmark, simple_benchmark, nested_benchmark, vec_benchmark);
+criterion_group!(benches, trivial_benchmark, simple_benchmark, simple_regex_benchmark, constrained_benchmark, nested_benchmark, vec_benchmark);
 criterion_main!(benches);
@@ -0,0 +1,178 @@
+#[macro_use]
+extern crate criterion;
+
+use criterion::Criterion;
+use rs_frame::{AppPath, Router};
+
+// Each line is a list route and a detail route, so 200 routes in total
+macro_rules! routes {
+    ($($list:ident, $detail:ident => $list_path:tt, $detail_path:tt;)*) => {
+        $(
+            #[derive(AppPath)]
+            #[path($list_path)]
+            struct $list {}
+
+            #[derive(AppPath)]
+            #[path($detail_path)]
+            struct $detail {
+                id: u64,
+            }
+        )*
+
+        fn router() -> Router<&'static str> {
+            let mut router = Router::new();
+
+            $(
+                router.add(|_: $list| stringify!($list));
+                router.add(|_: $detail| stringify!($detail));
+            )*
+
+            router
+        }
+
+        // What resolving a URL looks like without a router
+        fn linear_probe(app_path: &str) -> Option<&'static str> {
+            $(
+                if app_path.parse::<$list>().is_ok() {
+                    return Some(stringify!($list));
+                }
+
+                if app_path.parse::<$detail>().is_ok() {
+                    return Some(stringify!($detail));
+                }
+            )*
+
+            None
+        }
+    };
+}
+
+routes! {
+    Section000List, Section000Detail => "/section000/items", "/section000/items/:id";
+    Section001List, Section001Detail => "/section001/items", "/section001/items/:id";
+    Section002List, Section002Detail => "/section002/items", "/section002/items/:id";
+    Section003List, Section003Detail => "/section003/items", "/section003/items/:id";
+    Section004List, Section004Detail => "/section004/items", "/section004/items/:id";
+    Section005List, Section005Detail => "/section005/items", "/section005/items/:id";
+    Section006List, Section006Detail => "/section006/items", "/section006/items/:id";
+    Section007List, Section007Detail => "/section007/items", "/section007/items/:id";
+    Section008List, Section008Detail => "/section008/items", "/section008/items/:id";
+    Section009List, Section009Detail => "/section009/items", "/section009/items/:id";
+    Section010List, Section010Detail => "/section010/items", "/section010/items/:id";
+    Section011List, Section011Detail => "/section011/items", "/section011/items/:id";
+    Section012List, Section012Detail => "/section012/items", "/section012/items/:id";
+    Section013List, Section013Detail => "/section013/items", "/section013/items/:id";
+    Section014List, Section014Detail => "/section014/items", "/section014/items/:id";
+    Section015List, Section015Detail => "/section015/items", "/section015/items/:id";
+    Section016List, Section016Detail => "/section016/items", "/section016/items/:id";
+    Section017List, Section017Detail => "/section017/items", "/section017/items/:id";
+    Section018List, Section018Detail => "/section018/items", "/section018/items/:id";
+    Section019List, Section019Detail => "/section019/items", "/section019/items/:id";
+    Section020List, Section020Detail => "/section020/items", "/section020/items/:id";
+    Section021List, Section021Detail => "/section021/items", "/section021/items/:id";
+    Section022List, Section022Detail => "/section022/items", "/section022/items/:id";
+    Section023List, Section023Detail => "/section023/items", "/section023/items/:id";
+    Section024List, Section024Detail => "/section024/items", "/section024/items/:id";
+    Section025List, Section025Detail => "/section025/items", "/section025/items/:id";
+    Section026List, Section026Detail => "/section026/items", "/section026/items/:id";
+    Section027List, Section027Detail => "/section027/items", "/section027/items/:id";
+    Section028List, Section028Detail => "/section028/items", "/section028/items/:id";
+    Section029List, Section029Detail => "/section029/items", "/section029/items/:id";
+    Section030List, Section030Detail => "/section030/items", "/section030/items/:id";
+    Section031List, Section031Detail => "/section031/items", "/section031/items/:id";
+    Section032List, Section032Detail => "/section032/items", "/section032/items/:id";
+    Section033List, Section033Detail => "/section033/items", "/section033/items/:id";
+    Section034List, Section034Detail => "/section034/items", "/section034/items/:id";
+    Section035List, Section035Detail => "/section035/items", "/section035/items/:id";
+    Section036List, Section036Detail => "/section036/items", "/section036/items/:id";
+    Section037List, Section037Detail => "/section037/items", "/section037/items/:id";
+    Section038List, Section038Detail => "/section038/items", "/section038/items/:id";
+    Section039List, Section039Detail => "/section039/items", "/section039/items/:id";
+    Section040List, Section040Detail => "/section040/items", "/section040/items/:id";
+    Section041List, Section041Detail => "/section041/items", "/section041/items/:id";
+    Section042List, Section042Detail => "/section042/items", "/section042/items/:id";
+    Section043List, Section043Detail => "/section043/items", "/section043/items/:id";
+    Section044List, Section044Detail => "/section044/items", "/section044/items/:id";
+    Section045List, Section045Detail => "/section045/items", "/section045/items/:id";
+    Section046List, Section046Detail => "/section046/items", "/section046/items/:id";
+    Section047List, Section047Detail => "/section047/items", "/section047/items/:id";
+    Section048List, Section048Detail => "/section048/items", "/section048/items/:id";
+    Section049List, Section049Detail => "/section049/items", "/section049/items/:id";
+    Section050List, Section050Detail => "/section050/items", "/section050/items/:id";
+    Section051List, Section051Detail => "/section051/items", "/section051/items/:id";
+    Section052List, Section052Detail => "/section052/items", "/section052/items/:id";
+    Section053List, Section053Detail => "/section053/items", "/section053/items/:id";
+    Section054List, Section054Detail => "/section054/items", "/section054/items/:id";
+    Section055List, Section055Detail => "/section055/items", "/section055/items/:id";
+    Section056List, Section056Detail => "/section056/items", "/section056/items/:id";
+    Section057List, Section057Detail => "/section057/items", "/section057/items/:id";
+    Section058List, Section058Detail => "/section058/items", "/section058/items/:id";
+    Section059List, Section059Detail => "/section059/items", "/section059/items/:id";
+    Section060List, Section060Detail => "/section060/items", "/section060/items/:id";
+    Section061List, Section061Detail => "/section061/items", "/section061/items/:id";
+    Section062List, Section062Detail => "/section062/items", "/section062/items/:id";
+    Section063List, Section063Detail => "/section063/items", "/section063/items/:id";
+    Section064List, Section064Detail => "/section064/items", "/section064/items/:id";
+    Section065List, Section065Detail => "/section065/items", "/section065/items/:id";
+    Section066List, Section066Detail => "/section066/items", "/section066/items/:id";
+    Section067List, Section067Detail => "/section067/items", "/section067/items/:id";
+    Section068List, Section068Detail => "/section068/items", "/section068/items/:id";
+    Section069List, Section069Detail => "/section069/items", "/section069/items/:id";
+    Section070List, Section070Detail => "/section070/items", "/section070/items/:id";
+    Section071List, Section071Detail => "/section071/items", "/section071/items/:id";
+    Section072List, Section072Detail => "/section072/items", "/section072/items/:id";
+    Section073List, Section073Detail => "/section073/items", "/section073/items/:id";
+    Section074List, Section074Detail => "/section074/items", "/section074/items/:id";
+    Section075List, Section075Detail => "/section075/items", "/section075/items/:id";
+    Section076List, Section076Detail => "/section076/items", "/section076/items/:id";
+    Section077List, Section077Detail => "/section077/items", "/section077/items/:id";
+    Section078List, Section078Detail => "/section078/items", "/section078/items/:id";
+    Section079List, Section079Detail => "/section079/items", "/section079/items/:id";
+    Section080List, Section080Detail => "/section080/items", "/section080/items/:id";
+    Section081List, Section081Detail => "/section081/items", "/section081/items/:id";
+    Section082List, Section082Detail => "/section082/items", "/section082/items/:id";
+    Section083List, Section083Detail => "/section083/items", "/section083/items/:id";
+    Section084List, Section084Detail => "/section084/items", "/section084/items/:id";
+    Section085List, Section085Detail => "/section085/items", "/section085/items/:id";
+    Section086List, Section086Detail => "/section086/items", "/section086/items/:id";
+    Section087List, Section087Detail => "/section087/items", "/section087/items/:id";
+    Section088List, Section088Detail => "/section088/items", "/section088/items/:id";
+    Section089List, Section089Detail => "/section089/items", "/section089/items/:id";
+    Section090List, Section090Detail => "/section090/items", "/section090/items/:id";
+    Section091List, Section091Detail => "/section091/items", "/section091/items/:id";
+    Section092List, Section092Detail => "/section092/items", "/section092/items/:id";
+    Section093List, Section093Detail => "/section093/items", "/section093/items/:id";
+    Section094List, Section094Detail => "/section094/items", "/section094/items/:id";
+    Section095List, Section095Detail => "/section095/items", "/section095/items/:id";
+    Section096List, Section096Detail => "/section096/items", "/section096/items/:id";
+    Section097List, Section097Detail => "/section097/items", "/section097/items/:id";
+    Section098List, Section098Detail => "/section098/items", "/section098/items/:id";
+    Section099List, Section099Detail => "/section099/items", "/section099/items/:id";
+}
+
+fn router_benchmark(c: &mut Criterion) {
+    let router = router();
+
+    c.bench_function("Router first route", move |b| {
+        b.iter(|| router.route("/section000/items/642151").unwrap())
+    });
+
+    let router = self::router();
+
+    c.bench_function("Router last route", move |b| {
+        b.iter(|| router.route("/section099/items/642151").unwrap())
+    });
+}
+
+fn linear_probe_benchmark(c: &mut Criterion) {
+    c.bench_function("Linear probe first route", |b| {
+        b.iter(|| linear_probe("/section000/items/642151").unwrap())
+    });
+
+    c.bench_function("Linear probe last route", |b| {
+        b.iter(|| linear_probe("/section099/items/642151").unwrap())
+    });
+}
+
+criterion_group!(benches, router_benchmark, linear_probe_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,382 @@
+//! Runs controllers as the app navigates from URL to URL.
+
+use crate::codec::QueryEncoding;
+use crate::effect::EffectHandler;
+use crate::encoding::{decode_path_param, decode_query_param};
+use crate::AppPath;
+use regex::Regex;
+use std::any::{Any, TypeId};
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::rc::Rc;
+
+pub struct RouteData {
+    pub name: String,
+}
+
+/// The URL `App` is navigating to, broken into its parts for the controllers.
+pub struct RouteParams {
+    /// The first template of the paths known to the `App` which matches the
+    /// URL's path, like `/users/:user_id`, or `None` if none of them do
+    pub template: Option<String>,
+    /// The percent-decoded path params captured by `template`, by name.
+    /// Optional params which are missing are left out.
+    pub captures: BTreeMap<String, String>,
+    /// The percent-decoded pairs of the query string, in order, with `+`
+    /// read as a space
+    pub query: Vec<(String, String)>,
+    /// What comes after the `#`, if there's one
+    pub fragment: Option<String>,
+    pub data: RouteData,
+    /// The URL parsed into each `AppPath` type asked for so far, or `None`
+    /// if it didn't parse
+    paths: RefCell<HashMap<TypeId, Option<Rc<dyn Any>>>>,
+}
+
+impl RouteParams {
+    fn new(route: String, known_paths: &[KnownPath]) -> RouteParams {
+        let (url, fragment) = match route.find('#') {
+            Some(hash_pos) => (
+                &route[..hash_pos],
+                Some(route[(hash_pos + 1)..].to_string()),
+            ),
+            None => (&route[..], None),
+        };
+
+        let (just_path, query_string) = match url.find('?') {
+            Some(question_pos) => (&url[..question_pos], &url[(question_pos + 1)..]),
+            None => (url, ""),
+        };
+
+        let mut template = None;
+        let mut captures = BTreeMap::new();
+
+        let matched = known_paths
+            .iter()
+            .flat_map(|known_path| &known_path.templates)
+            .filter_map(|(template, regex)| Some((template, regex, regex.captures(just_path)?)))
+            .next();
+
+        if let Some((matched_template, regex, matched_captures)) = matched {
+            template = Some(matched_template.clone());
+
+            for name in regex.capture_names().flatten() {
+                if let Some(capture) = matched_captures.name(name) {
+                    let value = decode_path_param(capture.as_str())
+                        .unwrap_or_else(|_| capture.as_str().into());
+                    captures.insert(name.to_string(), value.into_owned());
+                }
+            }
+        }
+
+        let query = query_string
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (key, value) = match pair.find('=') {
+                    Some(equals_pos) => (&pair[..equals_pos], &pair[(equals_pos + 1)..]),
+                    None => (pair, ""),
+                };
+                let decode = |component: &str| {
+                    decode_query_param(component, QueryEncoding::Form)
+                        .map(|decoded| decoded.into_owned())
+                        .unwrap_or_else(|_| component.to_string())
+                };
+
+                (decode(key), decode(value))
+            })
+            .collect();
+
+        RouteParams {
+            template,
+            captures,
+            query,
+            fragment,
+            data: RouteData { name: route },
+            paths: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Parses the URL into `P`, or returns `None` if it doesn't parse. The
+    /// URL is only parsed once for each type, and the result is shared by
+    /// every controller which asks for it.
+    pub fn path<P: AppPath + 'static>(&self) -> Option<Rc<P>> {
+        let mut paths = self.paths.borrow_mut();
+
+        let path = paths.entry(TypeId::of::<P>()).or_insert_with(|| {
+            let name = &self.data.name;
+
+            name[..name.find('#').unwrap_or(name.len())]
+                .parse::<P>()
+                .ok()
+                .map(|path| Rc::new(path) as Rc<dyn Any>)
+        });
+
+        path.clone().map(|path| {
+            path.downcast::<P>()
+                .unwrap_or_else(|_| unreachable!("paths are keyed by their TypeId"))
+        })
+    }
+}
+
+pub trait Controller {
+    /// What `start` and `stop` ask the host to do, like loading some data
+    type Effect;
+
+    fn params(&mut self, params: &RouteParams) -> Option<()>;
+    fn start(&self) -> Vec<Self::Effect>;
+    fn stop(&self) -> Vec<Self::Effect> {
+        vec![]
+    }
+}
+
+/// A controller for the URLs which parse into `Path`. `App` hands it the
+/// parsed path whenever the URL changes, and stops it once the URL no longer
+/// parses.
+pub trait PathController {
+    type Path: AppPath + 'static;
+    type Effect;
+
+    fn params(&mut self, path: &Self::Path);
+    fn start(&self) -> Vec<Self::Effect>;
+    fn stop(&self) -> Vec<Self::Effect> {
+        vec![]
+    }
+}
+
+/// Runs a `PathController` as a `Controller`
+#[derive(Hash)]
+struct PathControllerAdapter<C>(C);
+
+impl<C: PathController> Controller for PathControllerAdapter<C> {
+    type Effect = C::Effect;
+
+    fn params(&mut self, params: &RouteParams) -> Option<()> {
+        let path = params.path::<C::Path>()?;
+        self.0.params(&path);
+
+        Some(())
+    }
+
+    fn start(&self) -> Vec<C::Effect> {
+        self.0.start()
+    }
+
+    fn stop(&self) -> Vec<C::Effect> {
+        self.0.stop()
+    }
+}
+
+/// A lifecycle method of `Controller`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lifecycle {
+    Start,
+    Stop,
+}
+
+/// One call `App` made to a controller while navigating
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LifecycleCall<E> {
+    /// The controller's index, counting the controllers in the order they
+    /// were added to the `App`
+    pub controller: usize,
+    pub call: Lifecycle,
+    /// The effects the call returned
+    pub emitted: Vec<E>,
+}
+
+/// What `App::new_route` did to get to the new route. The effects in it have
+/// already been handed to the `App`'s `EffectHandler`, if it has one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Transition<E> {
+    /// Every lifecycle call made, in the order they ran
+    pub calls: Vec<LifecycleCall<E>>,
+}
+
+impl<E> Transition<E> {
+    /// The calls made to the controller at `index`, in the order they ran
+    pub fn for_controller(&self, index: usize) -> impl Iterator<Item = &LifecycleCall<E>> {
+        self.calls
+            .iter()
+            .filter(move |call| call.controller == index)
+    }
+
+    /// Every effect the controllers emitted, in the order they were emitted
+    pub fn effects(&self) -> impl Iterator<Item = &E> {
+        self.calls.iter().flat_map(|call| &call.emitted)
+    }
+}
+
+impl<E> Default for Transition<E> {
+    fn default() -> Self {
+        Transition { calls: vec![] }
+    }
+}
+
+struct ControllerState<E> {
+    controller: Rc<RefCell<dyn Controller<Effect = E>>>,
+    prev_params: Option<u64>,
+    hasher: Box<Fn() -> u64>,
+}
+
+/// An `AppPath` type whose templates `App` matches URLs against for
+/// `RouteParams`
+struct KnownPath {
+    type_id: TypeId,
+    templates: Vec<(String, Regex)>,
+}
+
+/// Runs controllers whose effects are all `E`s
+pub struct App<E> {
+    controllers: Vec<ControllerState<E>>,
+    known_paths: Vec<KnownPath>,
+    effect_handler: Option<Box<dyn EffectHandler<E>>>,
+}
+
+impl<E: 'static> App<E> {
+    pub fn new() -> App<E> {
+        App {
+            controllers: vec![],
+            known_paths: vec![],
+            effect_handler: None,
+        }
+    }
+
+    /// Sets the handler which carries out the effects of every lifecycle
+    /// call, replacing the one set before
+    pub fn set_effect_handler<H: 'static + EffectHandler<E>>(&mut self, handler: H) {
+        self.effect_handler = Some(Box::new(handler));
+    }
+
+    /// Adds `P` to the paths whose templates fill in `RouteParams::template`
+    /// and `captures`. Paths are tried in the order they're added, and the
+    /// `Path` of each `PathController` is added along with it.
+    pub fn add_path<P: AppPath + 'static>(&mut self) {
+        let type_id = TypeId::of::<P>();
+
+        if self
+            .known_paths
+            .iter()
+            .any(|known_path| known_path.type_id == type_id)
+        {
+            return;
+        }
+
+        let templates = P::path_templates()
+            .into_iter()
+            .zip(P::path_patterns())
+            .map(|(template, pattern)| {
+                let regex = Regex::new(&pattern).expect("Failed to compile regex");
+                (template, regex)
+            })
+            .collect();
+
+        self.known_paths.push(KnownPath { type_id, templates });
+    }
+
+    pub fn add_controller<C: 'static + Controller<Effect = E> + Hash>(&mut self, controller: C) {
+        let controller_rc = Rc::new(RefCell::new(controller));
+
+        self.controllers.push(ControllerState {
+            controller: controller_rc.clone(),
+            prev_params: None,
+            hasher: Box::new(move || {
+                let mut s = DefaultHasher::new();
+                controller_rc.borrow_mut().hash(&mut s);
+                s.finish()
+            }),
+        });
+    }
+
+    pub fn add_path_controller<C: 'static + PathController<Effect = E> + Hash>(
+        &mut self,
+        controller: C,
+    ) {
+        self.add_path::<C::Path>();
+        self.add_controller(PathControllerAdapter(controller));
+    }
+
+    /// Navigates to `route` in three phases, so that no controller starts
+    /// while one which is leaving is still running:
+    ///
+    /// 1. Every controller is handed the new `RouteParams`, in the order they
+    ///    were added.
+    /// 2. The controllers which are leaving, or restarting with new params,
+    ///    are stopped in the reverse of the order they were added, so a
+    ///    controller stops before the ones added ahead of it.
+    /// 3. The controllers which are entering, or restarting, are started in
+    ///    the order they were added.
+    ///
+    /// The effects of each call are handed to the effect handler as soon as
+    /// it returns, so every effect of a stop is handled before any effect of
+    /// a start.
+    pub fn new_route(&mut self, route: String) -> Transition<E> {
+        let route_params = RouteParams::new(route, &self.known_paths);
+        let mut stopping = vec![];
+        let mut starting = vec![];
+
+        for (index, c) in self.controllers.iter_mut().enumerate() {
+            let new_params = { c.controller.borrow_mut().params(&route_params) };
+
+            let new_params = new_params.map(|_| (c.hasher)());
+
+            let (stop, start) = match (&c.prev_params, new_params) {
+                (None, None) => (false, false),
+                (Some(ref prev_params), Some(ref new_params)) if *prev_params == *new_params => {
+                    (false, false)
+                }
+                (None, Some(ref _new_params)) => (false, true),
+                (Some(ref _prev_params), None) => (true, false),
+                (Some(ref prev_params), Some(ref new_params)) if *prev_params != *new_params => {
+                    // Restart the controller
+                    (true, true)
+                }
+                _ => {
+                    unreachable!();
+                }
+            };
+
+            if stop {
+                stopping.push(index);
+            }
+            if start {
+                starting.push(index);
+            }
+
+            c.prev_params = new_params;
+        }
+
+        let calls = stopping
+            .into_iter()
+            .rev()
+            .map(|index| (index, Lifecycle::Stop))
+            .chain(starting.into_iter().map(|index| (index, Lifecycle::Start)));
+
+        let mut transition = Transition::default();
+
+        for (index, call) in calls {
+            let controller = self.controllers[index].controller.borrow();
+            let emitted = match call {
+                Lifecycle::Start => controller.start(),
+                Lifecycle::Stop => controller.stop(),
+            };
+
+            if let Some(ref mut effect_handler) = self.effect_handler {
+                for effect in &emitted {
+                    effect_handler.handle(effect);
+                }
+            }
+
+            transition.calls.push(LifecycleCall {
+                controller: index,
+                call,
+                emitted,
+            });
+        }
+
+        transition
+    }
+}
@@ -0,0 +1,850 @@
+//! Encodings for `#[query]` fields, picked with `#[query(codec = "...")]` on
+//! a field, or on the path struct to set the codec for all of its fields.
+//!
+//! `SerdeQs` is the default. All of them follow the `QueryEncoding` from
+//! `#[query(encoding = "...")]`. The others handle flat structs whose fields are
+//! scalars, `Option`s, unit enum variants and, depending on the codec,
+//! sequences of scalars.
+
+use crate::encoding::{decode_query_param, QUERY_PARAM_ENCODE_SET};
+use crate::{ErrorSource, QueryWriteErr};
+use percent_encoding::{define_encode_set, utf8_percent_encode};
+use serde::de::{self, DeserializeOwned, IntoDeserializer, Visitor};
+use serde::ser::{self, Serialize};
+use std::borrow::Cow;
+use std::fmt;
+use std::io;
+use std::str;
+
+/// How spaces and `+` signs are written in a query string, picked with
+/// `#[query(encoding = "form")]` or `#[query(encoding = "rfc3986")]`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum QueryEncoding {
+    /// The way browsers submit HTML forms: a space is written as `+`, and a
+    /// `+` is read as a space. This is the default.
+    #[default]
+    Form,
+    /// A space is written as `%20`, and a `+` is read as itself.
+    Rfc3986,
+}
+
+/// Turns a query string into a `#[query]` field and back again.
+pub trait QueryCodec {
+    /// Deserializes a query string, without the leading `?`.
+    fn decode<T: DeserializeOwned>(query: &str, encoding: QueryEncoding) -> Result<T, ErrorSource>;
+
+    /// Serializes `value` into `w` as a query string, without the leading `?`.
+    fn encode<T: Serialize, W: fmt::Write>(
+        value: &T,
+        encoding: QueryEncoding,
+        w: &mut W,
+    ) -> Result<(), QueryWriteErr>;
+}
+
+/// Nested structs and sequences in `serde_qs`'s bracket syntax, like
+/// `friend_ids[0]=1&friend_ids[1]=2`.
+pub struct SerdeQs;
+
+impl QueryCodec for SerdeQs {
+    fn decode<T: DeserializeOwned>(query: &str, encoding: QueryEncoding) -> Result<T, ErrorSource> {
+        // serde_qs always reads `+` as a space
+        let query = match encoding {
+            QueryEncoding::Form => Cow::Borrowed(query),
+            QueryEncoding::Rfc3986 => Cow::Owned(query.replace('+', "%2B")),
+        };
+
+        // serde_qs errors can't be shared across threads, so only their message is kept
+        serde_qs::from_str(&query).map_err(|e| e.to_string().into())
+    }
+
+    fn encode<T: Serialize, W: fmt::Write>(
+        value: &T,
+        encoding: QueryEncoding,
+        w: &mut W,
+    ) -> Result<(), QueryWriteErr> {
+        let mut writer = IoWriter { inner: w, encoding };
+
+        serde_qs::to_writer(value, &mut writer).map_err(|_| QueryWriteErr::Fmt)
+    }
+}
+
+/// Plain `application/x-www-form-urlencoded`, the way browsers submit HTML
+/// forms. Sequences aren't supported.
+pub struct FormUrlEncoded;
+
+impl QueryCodec for FormUrlEncoded {
+    fn decode<T: DeserializeOwned>(query: &str, encoding: QueryEncoding) -> Result<T, ErrorSource> {
+        Flat::new(Sequences::Unsupported, encoding).decode(query)
+    }
+
+    fn encode<T: Serialize, W: fmt::Write>(
+        value: &T,
+        encoding: QueryEncoding,
+        w: &mut W,
+    ) -> Result<(), QueryWriteErr> {
+        Flat::new(Sequences::Unsupported, encoding).encode(value, w)
+    }
+}
+
+/// Sequences as a repeated key, like `id=1&id=2`. An empty sequence leaves
+/// the key out, so give it `#[serde(default)]` to read it back.
+pub struct RepeatedKeys;
+
+impl QueryCodec for RepeatedKeys {
+    fn decode<T: DeserializeOwned>(query: &str, encoding: QueryEncoding) -> Result<T, ErrorSource> {
+        Flat::new(Sequences::RepeatedKeys, encoding).decode(query)
+    }
+
+    fn encode<T: Serialize, W: fmt::Write>(
+        value: &T,
+        encoding: QueryEncoding,
+        w: &mut W,
+    ) -> Result<(), QueryWriteErr> {
+        Flat::new(Sequences::RepeatedKeys, encoding).encode(value, w)
+    }
+}
+
+/// Sequences as a comma separated list, like `ids=1,2`. Commas inside the
+/// items are percent-encoded.
+pub struct CommaSeparated;
+
+impl QueryCodec for CommaSeparated {
+    fn decode<T: DeserializeOwned>(query: &str, encoding: QueryEncoding) -> Result<T, ErrorSource> {
+        Flat::new(Sequences::CommaSeparated, encoding).decode(query)
+    }
+
+    fn encode<T: Serialize, W: fmt::Write>(
+        value: &T,
+        encoding: QueryEncoding,
+        w: &mut W,
+    ) -> Result<(), QueryWriteErr> {
+        Flat::new(Sequences::CommaSeparated, encoding).encode(value, w)
+    }
+}
+
+/// serde_qs only knows how to write to an `io::Write`. Everything it writes
+/// is percent-encoded ASCII, so the bytes can be passed on as a `str`.
+struct IoWriter<'a, W> {
+    inner: &'a mut W,
+    encoding: QueryEncoding,
+}
+
+impl<'a, W: fmt::Write> io::Write for IoWriter<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let s = str::from_utf8(buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        // serde_qs always writes spaces as `+`, and encodes any other `+`
+        let written = match self.encoding {
+            QueryEncoding::Form => self.inner.write_str(s),
+            QueryEncoding::Rfc3986 => self.inner.write_str(&s.replace('+', "%20")),
+        };
+
+        written.map_err(|_| io::Error::other("formatter error"))?;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+define_encode_set! {
+    /// Like `QUERY_PARAM_ENCODE_SET`, but also encodes commas so they can
+    /// separate the items of a list.
+    pub QUERY_COMPONENT_ENCODE_SET = [QUERY_PARAM_ENCODE_SET] | {','}
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Sequences {
+    Unsupported,
+    RepeatedKeys,
+    CommaSeparated,
+}
+
+/// The settings shared by the codecs for flat structs
+#[derive(Clone, Copy)]
+struct Flat {
+    sequences: Sequences,
+    encoding: QueryEncoding,
+}
+
+impl Flat {
+    fn new(sequences: Sequences, encoding: QueryEncoding) -> Self {
+        Flat {
+            sequences,
+            encoding,
+        }
+    }
+
+    fn decode<T: DeserializeOwned>(self, query: &str) -> Result<T, ErrorSource> {
+        let mut pairs: Vec<(String, Vec<&str>)> = vec![];
+
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (key, value) = match pair.find('=') {
+                Some(equals_pos) => (&pair[..equals_pos], &pair[(equals_pos + 1)..]),
+                None => (pair, ""),
+            };
+
+            let key = self.decode_component(key)?;
+
+            match pairs.iter_mut().find(|(seen_key, _)| *seen_key == key) {
+                Some((_, values)) => values.push(value),
+                None => pairs.push((key, vec![value])),
+            }
+        }
+
+        T::deserialize(PairsDeserializer { pairs, flat: self }).map_err(|e| e.0.into())
+    }
+
+    fn encode<T: Serialize, W: fmt::Write>(
+        self,
+        value: &T,
+        w: &mut W,
+    ) -> Result<(), QueryWriteErr> {
+        let fields = match value.serialize(FlatSerializer(Level::Top)) {
+            Ok(Collected::Fields(fields)) => fields,
+            Ok(_) | Err(_) => return Err(QueryWriteErr::Fmt),
+        };
+
+        let mut written = false;
+
+        for (key, value) in fields {
+            let items = match value {
+                Collected::Skip => continue,
+                Collected::One(item) => vec![item],
+                Collected::Many(ref items) if self.sequences == Sequences::Unsupported => {
+                    if items.is_empty() {
+                        continue;
+                    }
+
+                    return Err(QueryWriteErr::Fmt);
+                }
+                Collected::Many(items) => items,
+                Collected::Fields(_) => return Err(QueryWriteErr::Fmt),
+            };
+
+            if self.sequences == Sequences::CommaSeparated {
+                if written {
+                    w.write_str("&")?;
+                }
+
+                written = true;
+                self.encode_component(&key, w)?;
+                w.write_str("=")?;
+
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        w.write_str(",")?;
+                    }
+
+                    self.encode_component(item, w)?;
+                }
+
+                continue;
+            }
+
+            for item in &items {
+                if written {
+                    w.write_str("&")?;
+                }
+
+                written = true;
+                self.encode_component(&key, w)?;
+                w.write_str("=")?;
+                self.encode_component(item, w)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn encode_component<W: fmt::Write>(self, component: &str, w: &mut W) -> fmt::Result {
+        for chunk in utf8_percent_encode(component, QUERY_COMPONENT_ENCODE_SET) {
+            if self.encoding == QueryEncoding::Form && chunk == "%20" {
+                w.write_str("+")?;
+            } else {
+                w.write_str(chunk)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn decode_component(self, component: &str) -> Result<String, Error> {
+        decode_query_param(component, self.encoding)
+            .map(|decoded| decoded.into_owned())
+            .map_err(de::Error::custom)
+    }
+}
+
+/// The error used while (de)serializing flat structs
+#[derive(Debug)]
+struct Error(String);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error(msg.to_string())
+    }
+}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error(msg.to_string())
+    }
+}
+
+/// Deserializes a struct or map from the pairs of a query string, with the
+/// values of repeated keys grouped together and still encoded
+struct PairsDeserializer<'a> {
+    pairs: Vec<(String, Vec<&'a str>)>,
+    flat: Flat,
+}
+
+impl<'de, 'a> de::Deserializer<'de> for PairsDeserializer<'a> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_map(PairsAccess {
+            pairs: self.pairs.into_iter(),
+            value: None,
+            flat: self.flat,
+        })
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+struct PairsAccess<'a> {
+    pairs: std::vec::IntoIter<(String, Vec<&'a str>)>,
+    value: Option<Vec<&'a str>>,
+    flat: Flat,
+}
+
+impl<'de, 'a> de::MapAccess<'de> for PairsAccess<'a> {
+    type Error = Error;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        match self.pairs.next() {
+            Some((key, values)) => {
+                self.value = Some(values);
+                seed.deserialize(key.into_deserializer()).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let values = self.value.take().expect("value requested before its key");
+
+        seed.deserialize(ValueDeserializer {
+            values,
+            flat: self.flat,
+        })
+    }
+}
+
+/// Deserializes the still encoded values of one key
+struct ValueDeserializer<'a> {
+    values: Vec<&'a str>,
+    flat: Flat,
+}
+
+impl<'a> ValueDeserializer<'a> {
+    fn first(&self) -> Result<PartDeserializer, Error> {
+        self.flat
+            .decode_component(self.values[0])
+            .map(PartDeserializer)
+    }
+}
+
+macro_rules! forward_to_first {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                self.first()?.$method(visitor)
+            }
+        )*
+    };
+}
+
+impl<'de, 'a> de::Deserializer<'de> for ValueDeserializer<'a> {
+    type Error = Error;
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let flat = self.flat;
+        let raw_items: Vec<&str> =
+            match self.flat.sequences {
+                Sequences::Unsupported => return Err(de::Error::custom(
+                    "sequences aren't supported by this codec, try RepeatedKeys or CommaSeparated",
+                )),
+                Sequences::RepeatedKeys => self.values,
+                Sequences::CommaSeparated => self
+                    .values
+                    .iter()
+                    .filter(|value| !value.is_empty())
+                    .flat_map(|value| value.split(','))
+                    .collect(),
+            };
+
+        let items = raw_items
+            .into_iter()
+            .map(|item| flat.decode_component(item).map(PartDeserializer))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        visitor.visit_seq(de::value::SeqDeserializer::new(items.into_iter()))
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.first()?.deserialize_enum(name, variants, visitor)
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.first()?.deserialize_unit_struct(name, visitor)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Error> {
+        Err(de::Error::custom(
+            "nested structs aren't supported by this codec",
+        ))
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Error> {
+        Err(de::Error::custom(
+            "nested maps aren't supported by this codec",
+        ))
+    }
+
+    forward_to_first! {
+        deserialize_any deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32
+        deserialize_i64 deserialize_i128 deserialize_u8 deserialize_u16 deserialize_u32
+        deserialize_u64 deserialize_u128 deserialize_f32 deserialize_f64 deserialize_char
+        deserialize_str deserialize_string deserialize_bytes deserialize_byte_buf
+        deserialize_unit deserialize_identifier deserialize_ignored_any
+    }
+}
+
+/// Deserializes a single decoded value, parsing it for anything which isn't a string
+struct PartDeserializer(String);
+
+impl<'de> IntoDeserializer<'de, Error> for PartDeserializer {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                visitor.$visit(self.0.parse().map_err(|e| {
+                    de::Error::custom(format_args!("couldn't parse `{}`: {}", self.0, e))
+                })?)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for PartDeserializer {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_string(self.0)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let deserializer: de::value::StringDeserializer<Error> = self.0.into_deserializer();
+        deserializer.deserialize_enum(name, variants, visitor)
+    }
+
+    deserialize_parsed! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_i128 => visit_i128,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_u128 => visit_u128,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    serde::forward_to_deserialize_any! {
+        str string bytes byte_buf unit unit_struct seq tuple tuple_struct map
+        struct identifier ignored_any
+    }
+}
+
+/// What a flat struct serializes into
+enum Collected {
+    /// A `None`, which leaves the key out
+    Skip,
+    One(String),
+    Many(Vec<String>),
+    Fields(Vec<(String, Collected)>),
+}
+
+/// How deep into the struct being serialized we are, which decides what's allowed
+#[derive(Clone, Copy, PartialEq)]
+enum Level {
+    Top,
+    Field,
+    Item,
+}
+
+struct FlatSerializer(Level);
+
+impl FlatSerializer {
+    fn scalar<T: fmt::Display>(self, value: T) -> Result<Collected, Error> {
+        if self.0 == Level::Top {
+            return Err(ser::Error::custom("a query must be a struct or map"));
+        }
+
+        Ok(Collected::One(value.to_string()))
+    }
+}
+
+macro_rules! serialize_display {
+    ($($method:ident: $ty:ty,)*) => {
+        $(
+            fn $method(self, value: $ty) -> Result<Collected, Error> {
+                self.scalar(value)
+            }
+        )*
+    };
+}
+
+impl ser::Serializer for FlatSerializer {
+    type Ok = Collected;
+    type Error = Error;
+    type SerializeSeq = SeqCollector;
+    type SerializeTuple = SeqCollector;
+    type SerializeTupleStruct = SeqCollector;
+    type SerializeTupleVariant = ser::Impossible<Collected, Error>;
+    type SerializeMap = FieldsCollector;
+    type SerializeStruct = FieldsCollector;
+    type SerializeStructVariant = ser::Impossible<Collected, Error>;
+
+    serialize_display! {
+        serialize_bool: bool,
+        serialize_i8: i8,
+        serialize_i16: i16,
+        serialize_i32: i32,
+        serialize_i64: i64,
+        serialize_i128: i128,
+        serialize_u8: u8,
+        serialize_u16: u16,
+        serialize_u32: u32,
+        serialize_u64: u64,
+        serialize_u128: u128,
+        serialize_f32: f32,
+        serialize_f64: f64,
+        serialize_char: char,
+        serialize_str: &str,
+    }
+
+    fn serialize_bytes(self, _value: &[u8]) -> Result<Collected, Error> {
+        Err(ser::Error::custom("bytes aren't supported by this codec"))
+    }
+
+    fn serialize_none(self) -> Result<Collected, Error> {
+        Ok(Collected::Skip)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Collected, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Collected, Error> {
+        self.scalar("")
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Collected, Error> {
+        self.scalar("")
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Collected, Error> {
+        self.scalar(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Collected, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Collected, Error> {
+        Err(ser::Error::custom(
+            "enum variants with data aren't supported by this codec",
+        ))
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<SeqCollector, Error> {
+        if self.0 != Level::Field {
+            return Err(ser::Error::custom("only fields can be sequences"));
+        }
+
+        Ok(SeqCollector(vec![]))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqCollector, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SeqCollector, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        Err(ser::Error::custom(
+            "enum variants with data aren't supported by this codec",
+        ))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<FieldsCollector, Error> {
+        if self.0 != Level::Top {
+            return Err(ser::Error::custom(
+                "nested maps aren't supported by this codec",
+            ));
+        }
+
+        Ok(FieldsCollector {
+            fields: vec![],
+            key: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<FieldsCollector, Error> {
+        if self.0 != Level::Top {
+            return Err(ser::Error::custom(
+                "nested structs aren't supported by this codec",
+            ));
+        }
+
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        Err(ser::Error::custom(
+            "enum variants with data aren't supported by this codec",
+        ))
+    }
+}
+
+struct SeqCollector(Vec<String>);
+
+impl SeqCollector {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        match value.serialize(FlatSerializer(Level::Item))? {
+            Collected::One(item) => self.0.push(item),
+            Collected::Skip => {}
+            Collected::Many(_) | Collected::Fields(_) => unreachable!("items can only be scalars"),
+        }
+
+        Ok(())
+    }
+}
+
+impl ser::SerializeSeq for SeqCollector {
+    type Ok = Collected;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Collected, Error> {
+        Ok(Collected::Many(self.0))
+    }
+}
+
+impl ser::SerializeTuple for SeqCollector {
+    type Ok = Collected;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Collected, Error> {
+        Ok(Collected::Many(self.0))
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqCollector {
+    type Ok = Collected;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Collected, Error> {
+        Ok(Collected::Many(self.0))
+    }
+}
+
+struct FieldsCollector {
+    fields: Vec<(String, Collected)>,
+    key: Option<String>,
+}
+
+impl ser::SerializeMap for FieldsCollector {
+    type Ok = Collected;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        match key.serialize(FlatSerializer(Level::Item))? {
+            Collected::One(key) => {
+                self.key = Some(key);
+                Ok(())
+            }
+            _ => Err(ser::Error::custom("map keys must be scalars")),
+        }
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self.key.take().expect("value serialized before its key");
+        self.fields
+            .push((key, value.serialize(FlatSerializer(Level::Field))?));
+
+        Ok(())
+    }
+
+    fn end(self) -> Result<Collected, Error> {
+        Ok(Collected::Fields(self.fields))
+    }
+}
+
+impl ser::SerializeStruct for FieldsCollector {
+    type Ok = Collected;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.fields.push((
+            key.to_string(),
+            value.serialize(FlatSerializer(Level::Field))?,
+        ));
+
+        Ok(())
+    }
+
+    fn end(self) -> Result<Collected, Error> {
+        Ok(Collected::Fields(self.fields))
+    }
+}
@@ -0,0 +1,60 @@
+//! Carrying out the effects controllers emit from `start` and `stop`.
+//!
+//! Controllers describe what should happen as values of their `Effect` type,
+//! and the `EffectHandler` set with `App::set_effect_handler` is handed each
+//! one as soon as the lifecycle call emitting it returns.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Carries out the effects emitted by an `App`'s controllers.
+pub trait EffectHandler<E> {
+    fn handle(&mut self, effect: &E);
+}
+
+impl<E, F: FnMut(&E)> EffectHandler<E> for F {
+    fn handle(&mut self, effect: &E) {
+        self(effect)
+    }
+}
+
+/// Keeps every effect it's handed instead of carrying it out, for tests.
+/// Clones share the same record, so keep one to look at after handing the
+/// other to the `App`.
+#[derive(Debug)]
+pub struct RecordingHandler<E> {
+    effects: Rc<RefCell<Vec<E>>>,
+}
+
+impl<E> RecordingHandler<E> {
+    pub fn new() -> Self {
+        RecordingHandler {
+            effects: Rc::new(RefCell::new(vec![])),
+        }
+    }
+
+    /// Takes the effects recorded so far, in the order they were handled
+    pub fn take(&self) -> Vec<E> {
+        self.effects.replace(vec![])
+    }
+}
+
+impl<E> Clone for RecordingHandler<E> {
+    fn clone(&self) -> Self {
+        RecordingHandler {
+            effects: self.effects.clone(),
+        }
+    }
+}
+
+impl<E> Default for RecordingHandler<E> {
+    fn default() -> Self {
+        RecordingHandler::new()
+    }
+}
+
+impl<E: Clone> EffectHandler<E> for RecordingHandler<E> {
+    fn handle(&mut self, effect: &E) {
+        self.effects.borrow_mut().push(effect.clone());
+    }
+}
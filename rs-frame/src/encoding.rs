@@ -0,0 +1,170 @@
+use crate::codec::QueryEncoding;
+use crate::{ParamParseErr, PathParseErr};
+use percent_encoding::{
+    define_encode_set, percent_decode, utf8_percent_encode, DEFAULT_ENCODE_SET,
+    PATH_SEGMENT_ENCODE_SET, QUERY_ENCODE_SET,
+};
+use std::borrow::Cow;
+use std::fmt;
+use std::str::{self, FromStr};
+
+define_encode_set! {
+    /// Like `PATH_SEGMENT_ENCODE_SET`, but leaves slashes alone so a splat
+    /// param can span multiple segments.
+    pub SPLAT_ENCODE_SET = [DEFAULT_ENCODE_SET] | {'%'}
+}
+
+define_encode_set! {
+    /// Like `QUERY_ENCODE_SET`, but also encodes the characters which would
+    /// end a key or value early or change its meaning.
+    pub QUERY_PARAM_ENCODE_SET = [QUERY_ENCODE_SET] | {'&', '=', '+', '%', '`'}
+}
+
+/// Percent-decodes a captured path param before it gets parsed.
+pub fn decode_path_param(param: &str) -> Result<Cow<'_, str>, str::Utf8Error> {
+    percent_decode(param.as_bytes()).decode_utf8()
+}
+
+/// Percent-decodes a key or value from the query string, reading `+` as a
+/// space when `encoding` is `Form`.
+pub fn decode_query_param(
+    param: &str,
+    encoding: QueryEncoding,
+) -> Result<Cow<'_, str>, str::Utf8Error> {
+    match encoding {
+        QueryEncoding::Form if param.contains('+') => {
+            percent_decode(param.replace('+', " ").as_bytes())
+                .decode_utf8()
+                .map(|decoded| Cow::Owned(decoded.into_owned()))
+        }
+        _ => decode_path_param(param),
+    }
+}
+
+/// Percent-decodes a captured path param and parses it into the type of
+/// `field`, recording where things went wrong if either step fails.
+pub fn parse_path_param<T>(
+    param: &str,
+    field: &'static str,
+    pattern: &'static str,
+) -> Result<T, PathParseErr>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    let param_err = |source| {
+        PathParseErr::ParamParseErr(ParamParseErr {
+            field,
+            segment: param.to_string(),
+            pattern,
+            source,
+        })
+    };
+
+    decode_path_param(param)
+        .map_err(|e| param_err(e.into()))?
+        .parse()
+        .map_err(|e: T::Err| param_err(e.to_string().into()))
+}
+
+/// Percent-encodes everything written to it before passing it on to the
+/// inner writer, so a value can be encoded without formatting it to a
+/// `String` first.
+struct EncodingWriter<'a, W, E> {
+    inner: &'a mut W,
+    encode_set: E,
+    /// Writes spaces as `+` instead of `%20`
+    plus_for_space: bool,
+}
+
+impl<'a, W: fmt::Write, E: percent_encoding::EncodeSet> fmt::Write for EncodingWriter<'a, W, E> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for chunk in utf8_percent_encode(s, self.encode_set.clone()) {
+            if self.plus_for_space && chunk == "%20" {
+                self.inner.write_str("+")?;
+            } else {
+                self.inner.write_str(chunk)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Displays a path param as a single percent-encoded path segment.
+pub struct EncodePathParam<'a, T>(pub &'a T);
+
+impl<'a, T: fmt::Display> fmt::Display for EncodePathParam<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut writer = EncodingWriter {
+            inner: f,
+            encode_set: PATH_SEGMENT_ENCODE_SET,
+            plus_for_space: false,
+        };
+
+        fmt::Write::write_fmt(&mut writer, format_args!("{}", self.0))
+    }
+}
+
+/// Displays an optional path param along with the slash in front of it, or
+/// nothing at all if it's missing.
+pub struct EncodeOptionalParam<'a, T>(pub Option<&'a T>);
+
+impl<'a, T: fmt::Display> fmt::Display for EncodeOptionalParam<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(param) => write!(f, "/{}", EncodePathParam(param)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Displays a splat param, encoding each segment but keeping the slashes
+/// between them.
+pub struct EncodeSplatParam<'a, T>(pub &'a T);
+
+impl<'a, T: fmt::Display> fmt::Display for EncodeSplatParam<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut writer = EncodingWriter {
+            inner: f,
+            encode_set: SPLAT_ENCODE_SET,
+            plus_for_space: false,
+        };
+
+        fmt::Write::write_fmt(&mut writer, format_args!("{}", self.0))
+    }
+}
+
+/// Displays the segments of a `Vec` splat param joined by slashes, encoding
+/// each one on its own.
+/// An empty `Vec` writes nothing, which parses back as an empty splat.
+pub struct EncodeSplatSegments<'a, T>(pub &'a [T]);
+
+impl<'a, T: fmt::Display> fmt::Display for EncodeSplatSegments<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, segment) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str("/")?;
+            }
+
+            write!(f, "{}", EncodePathParam(segment))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Displays the key or value of a `#[query_param]`, percent-encoded.
+pub struct EncodeQueryParam<'a, T: ?Sized>(pub &'a T, pub QueryEncoding);
+
+impl<'a, T: fmt::Display + ?Sized> fmt::Display for EncodeQueryParam<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut writer = EncodingWriter {
+            inner: f,
+            encode_set: QUERY_PARAM_ENCODE_SET,
+            plus_for_space: self.1 == QueryEncoding::Form,
+        };
+
+        fmt::Write::write_fmt(&mut writer, format_args!("{}", self.0))
+    }
+}
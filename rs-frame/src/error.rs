@@ -0,0 +1,163 @@
+use std::error::Error;
+use std::fmt;
+
+/// The underlying error from parsing a param or query string
+pub type ErrorSource = Box<dyn Error + Send + Sync>;
+
+#[derive(Debug)]
+pub enum PathParseErr {
+    /// The path didn't match the pattern
+    NoMatches,
+    /// A required `#[query]` or `#[query_param]` field had no query string to parse
+    NoQueryString,
+    /// A path param matched but couldn't be parsed into its field
+    ParamParseErr(ParamParseErr),
+    /// The query string couldn't be parsed into a `#[query]` or `#[query_param]` field
+    QueryParseErr(QueryParseErr),
+    /// The path has `#[query(deny_unknown)]` and the query string had keys
+    /// none of its query fields read. The keys are percent-decoded.
+    UnknownQueryKeys(Vec<String>),
+}
+
+impl fmt::Display for PathParseErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PathParseErr::NoMatches => write!(f, "the path didn't match"),
+            PathParseErr::NoQueryString => write!(f, "the query string is missing"),
+            PathParseErr::ParamParseErr(e) => e.fmt(f),
+            PathParseErr::QueryParseErr(e) => e.fmt(f),
+            PathParseErr::UnknownQueryKeys(keys) => {
+                write!(f, "the query string has unknown keys: ")?;
+
+                for (i, key) in keys.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+
+                    write!(f, "`{}`", key)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Error for PathParseErr {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PathParseErr::NoMatches
+            | PathParseErr::NoQueryString
+            | PathParseErr::UnknownQueryKeys(_) => None,
+            PathParseErr::ParamParseErr(e) => e.source(),
+            PathParseErr::QueryParseErr(e) => e.source(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ParamParseErr {
+    /// The field the param was parsed into
+    pub field: &'static str,
+    /// The text of the param as it appeared in the path, still percent-encoded
+    pub segment: String,
+    /// The `#[path]` pattern that matched
+    pub pattern: &'static str,
+    /// The error from percent-decoding the param or from its field's
+    /// `FromStr`. A `FromStr` error is carried as its message, since it only
+    /// has to implement `Display`.
+    pub source: ErrorSource,
+}
+
+impl fmt::Display for ParamParseErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "couldn't parse `{}` into `{}` for `{}`: {}",
+            self.segment, self.field, self.pattern, self.source
+        )
+    }
+}
+
+impl Error for ParamParseErr {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&*self.source)
+    }
+}
+
+#[derive(Debug)]
+pub struct QueryParseErr {
+    /// The `#[query]` or `#[query_param]` field being parsed
+    pub field: &'static str,
+    /// The key of a `#[query_param]` field, or `None` for a `#[query]` field
+    /// which is parsed from the whole query string
+    pub key: Option<&'static str>,
+    /// The query string, without the leading `?`
+    pub query: String,
+    /// The `#[path]` pattern that matched
+    pub pattern: &'static str,
+    /// The error from the field's `QueryCodec`, or from the `FromStr` of a
+    /// `#[query_param]` field. A `serde_qs` error is carried as its message,
+    /// because it can't be shared across threads, and so is a `FromStr`
+    /// error, which only has to implement `Display`.
+    pub source: ErrorSource,
+}
+
+impl fmt::Display for QueryParseErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.key {
+            Some(key) => write!(
+                f,
+                "couldn't parse `{}` in the query string `{}` into `{}` for `{}`: {}",
+                key, self.query, self.field, self.pattern, self.source
+            ),
+            None => write!(
+                f,
+                "couldn't parse the query string `{}` into `{}` for `{}`: {}",
+                self.query, self.field, self.pattern, self.source
+            ),
+        }
+    }
+}
+
+impl Error for QueryParseErr {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&*self.source)
+    }
+}
+
+/// An error from writing the query string of a path
+#[derive(Debug)]
+pub enum QueryWriteErr {
+    /// The writer failed, or a query field couldn't be serialized
+    Fmt,
+    /// Two query fields wrote different values for the same key. The values
+    /// are percent-decoded, and a key repeated within one field has its
+    /// values joined with commas.
+    Conflict {
+        key: String,
+        first: String,
+        second: String,
+    },
+}
+
+impl From<fmt::Error> for QueryWriteErr {
+    fn from(_: fmt::Error) -> QueryWriteErr {
+        QueryWriteErr::Fmt
+    }
+}
+
+impl fmt::Display for QueryWriteErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            QueryWriteErr::Fmt => write!(f, "the query string couldn't be written"),
+            QueryWriteErr::Conflict { key, first, second } => write!(
+                f,
+                "query fields disagree on `{}`, one has `{}` and another has `{}`",
+                key, first, second
+            ),
+        }
+    }
+}
+
+impl Error for QueryWriteErr {}
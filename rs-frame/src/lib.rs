@@ -1,8 +1,13 @@
-use std::cell::RefCell;
-use std::collections::hash_map::DefaultHasher;
-use std::hash::Hash;
-use std::hash::Hasher;
-use std::rc::Rc;
+use std::fmt::Write;
+
+mod app;
+pub mod codec;
+pub mod effect;
+mod encoding;
+mod error;
+mod matcher;
+mod query;
+mod router;
 
 #[doc(hidden)]
 pub use lazy_static::lazy_static;
@@ -13,115 +18,147 @@ pub use regex::Regex;
 #[doc(hidden)]
 pub use serde_qs;
 
-pub use rs_frame_macros::AppPath;
+#[doc(hidden)]
+pub use encoding::{
+    parse_path_param, EncodeOptionalParam, EncodePathParam, EncodeSplatParam, EncodeSplatSegments,
+};
 
-#[derive(Debug)]
-pub enum PathParseErr {
-    NoMatches,
-    NoQueryString,
-    ParamParseErr(String),
-    QueryParseErr(String),
-}
+#[doc(hidden)]
+pub use matcher::{
+    match_literal, match_optional_param, match_param, match_param_before, match_splat,
+    splat_segments, unname_groups,
+};
+
+#[doc(hidden)]
+pub use query::{
+    deny_unknown_query_keys, parse_query, parse_query_param, parse_required_query_param,
+    query_has_keys, query_keys, unknown_query_pairs, LazyPrefix, QueryWriter,
+};
+
+pub use codec::{QueryCodec, QueryEncoding};
+
+pub use effect::{EffectHandler, RecordingHandler};
+
+pub use error::{ErrorSource, ParamParseErr, PathParseErr, QueryParseErr, QueryWriteErr};
+
+pub use app::{
+    App, Controller, Lifecycle, LifecycleCall, PathController, RouteData, RouteParams, Transition,
+};
+
+pub use router::{RouteConflict, Router};
+
+pub use rs_frame_macros::AppPath;
 
 pub trait AppPath: std::fmt::Display + std::str::FromStr {
+    /// The regex matching the paths this type parses. Enums join the regexes
+    /// of their variants, without naming the groups, so use `path_patterns`
+    /// to get at the params.
     fn path_pattern() -> String
     where
         Self: Sized;
-    fn query_string(&self) -> Option<String>;
-}
 
-pub struct RouteData {
-    pub name: String,
-}
+    /// The `#[path]` templates this type parses, like `/users/:user_id`.
+    /// Enums return one for each variant, in the order they're tried.
+    fn path_templates() -> Vec<String>
+    where
+        Self: Sized;
 
-pub struct RouteParams {
-    pub template: String,
-    pub data: RouteData,
-}
+    /// The regex for each of `path_templates`, in the same order, with a
+    /// named group for each param.
+    fn path_patterns() -> Vec<String>
+    where
+        Self: Sized,
+    {
+        vec![Self::path_pattern()]
+    }
 
-pub trait Controller {
-    fn params(&mut self, params: &RouteParams) -> Option<()>;
-    fn start(&self) -> Vec<String>;
-    fn stop(&self) -> Vec<String> {
-        vec![]
+    /// Writes the query string, without the leading `?`, straight into `w`.
+    /// When several query fields write the same key it only shows up once,
+    /// and if they disagree on its value this returns a `Conflict`.
+    fn write_query(&self, w: &mut impl std::fmt::Write) -> Result<(), QueryWriteErr>;
+
+    /// Writes the query string for `Display`. Unlike `write_query`, query
+    /// fields which disagree on a key aren't an error: the value of the
+    /// field declared first is written.
+    #[doc(hidden)]
+    fn write_display_query(&self, w: &mut impl std::fmt::Write) -> Result<(), QueryWriteErr> {
+        self.write_query(w)
     }
-}
 
-struct ControllerState {
-    controller: Rc<RefCell<dyn Controller>>,
-    prev_params: Option<u64>,
-    hasher: Box<Fn() -> u64>,
-}
+    /// Whether the path only parses with a query string, even an empty one,
+    /// because it has a `#[query]` field which isn't an `Option`. `Display`
+    /// and `canonical_url` write a `?` for these paths even when the query
+    /// string is empty.
+    #[doc(hidden)]
+    fn requires_query(&self) -> bool {
+        false
+    }
 
-pub struct App {
-    controllers: Vec<ControllerState>,
-}
+    /// Writes the query string like `write_query`, but sorted by key, so
+    /// paths which are equal always write the same query string. The derived
+    /// impl also leaves out the empty values of a `#[query]` field, and the
+    /// values its type reads from an empty query string, like `false` for a
+    /// `#[serde(default)]` bool, as long as the field reads the same value
+    /// back without them. Paths with `#[query(canonical)]` write their query
+    /// string this way everywhere, including `Display`.
+    fn write_canonical_query(&self, w: &mut impl std::fmt::Write) -> Result<(), QueryWriteErr> {
+        let mut query = String::new();
+        self.write_query(&mut query)?;
+
+        let mut writer = QueryWriter::canonical(w);
+        writer.write_encoded(&query)?;
+        writer.finish()
+    }
 
-impl App {
-    pub fn new() -> App {
-        App {
-            controllers: vec![],
+    /// The path followed by its canonical query string, for cache keys and
+    /// `rel=canonical` links. The query string is left out if the query
+    /// fields conflict.
+    fn canonical_url(&self) -> String {
+        let mut url = String::new();
+        let _ = write!(url, "{}", self);
+        url.truncate(url.find('?').unwrap_or(url.len()));
+
+        let mut query = String::new();
+
+        if self.write_canonical_query(&mut query).is_ok()
+            && (!query.is_empty() || self.requires_query())
+        {
+            url.push('?');
+            url.push_str(&query);
         }
-    }
 
-    pub fn add_controller<C: 'static + Controller + Hash>(&mut self, controller: C) {
-        let controller_rc = Rc::new(RefCell::new(controller));
-
-        self.controllers.push(ControllerState {
-            controller: controller_rc.clone(),
-            prev_params: None,
-            hasher: Box::new(move || {
-                let mut s = DefaultHasher::new();
-                controller_rc.borrow_mut().hash(&mut s);
-                s.finish()
-            }),
-        });
+        url
     }
 
-    pub fn new_route(&mut self, route: String) {
-        println!("new route: {}", route);
-
-        let route_params = RouteParams {
-            template: "/whatever".to_string(),
-            data: RouteData { name: route },
-        };
-
-        for c in &mut self.controllers {
-            let new_params = { c.controller.borrow_mut().params(&route_params) };
-
-            let new_params = new_params.map(|_| (c.hasher)());
-
-            // println!("new_params is {:?}", new_params);
-
-            match (&c.prev_params, new_params) {
-                (None, None) => {
-                    // println!("Do nothing");
-                }
-                (Some(ref prev_params), Some(ref new_params)) if *prev_params == *new_params => {
-                    // println!("Do nothing");
-                }
-                (None, Some(ref _new_params)) => {
-                    // println!("Call start");
-                    c.controller.borrow().start();
-                }
-                (Some(ref _prev_params), None) => {
-                    // println!("Call stop");
-                    c.controller.borrow().stop();
-                }
-                (Some(ref prev_params), Some(ref new_params)) if *prev_params != *new_params => {
-                    // Restart the controller
-                    // println!("Call stop, then start");
-                    c.controller.borrow().stop();
-                    c.controller.borrow().start();
-                }
-                _ => {
-                    unreachable!();
-                }
-            }
-
-            c.prev_params = new_params;
+    /// The path and query string like `Display` writes them, but with a
+    /// `Conflict` instead of picking a value when query fields disagree on
+    /// a key.
+    fn try_to_string(&self) -> Result<String, QueryWriteErr> {
+        let mut url = String::new();
+        write!(url, "{}", self)?;
+        url.truncate(url.find('?').unwrap_or(url.len()));
+
+        if self.requires_query() {
+            url.push('?');
+            self.write_query(&mut url)?;
+        } else {
+            self.write_query(&mut LazyPrefix::new(&mut url, "?"))?;
         }
 
-        println!();
+        Ok(url)
+    }
+
+    /// The query string like `Display` writes it, without the leading `?`, or
+    /// `None` if it's empty. When query fields disagree on a key, the value
+    /// of the field declared first is used.
+    fn query_string(&self) -> Option<String> {
+        let mut query = String::new();
+        self.write_display_query(&mut query).ok()?;
+
+        if !query.is_empty() {
+            Some(query)
+        } else {
+            None
+        }
     }
 }
@@ -0,0 +1,164 @@
+//! Helpers for the matchers generated by `#[derive(AppPath)]`, which walk the
+//! path directly instead of going through a regex. Each one takes the part of
+//! the path which hasn't been matched yet and returns what's left after it.
+
+/// Strips static text off the front of the path.
+pub fn match_literal<'a>(path: &'a str, literal: &str) -> Option<&'a str> {
+    path.strip_prefix(literal)
+}
+
+/// Splits a param off the front of the path. The param ends at the first
+/// occurrence of `until` within the current segment, or at the end of the
+/// segment if `until` is empty. Params always match at least one character.
+///
+/// Ending at the first occurrence only agrees with the regex when another
+/// param follows `until` in the segment. Use `match_param_before` when
+/// `until` ends the segment.
+pub fn match_param<'a>(path: &'a str, until: &str) -> Option<(&'a str, &'a str)> {
+    let segment = current_segment(path);
+
+    let end = if until.is_empty() {
+        segment.len()
+    } else {
+        let first_char_len = segment.chars().next()?.len_utf8();
+        segment[first_char_len..].find(until)? + first_char_len
+    };
+
+    if end == 0 {
+        return None;
+    }
+
+    Some((&path[..end], &path[end..]))
+}
+
+/// Splits a param off the front of the path when the current segment ends
+/// with `suffix` right after it, like `:name` in `/files/:name.tar.gz`. The
+/// param takes everything in the segment up to the suffix, even if the
+/// suffix shows up earlier in it.
+pub fn match_param_before<'a>(path: &'a str, suffix: &str) -> Option<(&'a str, &'a str)> {
+    let segment = current_segment(path);
+
+    if !segment.ends_with(suffix) || segment.len() == suffix.len() {
+        return None;
+    }
+
+    let end = segment.len() - suffix.len();
+
+    Some((&path[..end], &path[end..]))
+}
+
+fn current_segment(path: &str) -> &str {
+    &path[..path.find('/').unwrap_or(path.len())]
+}
+
+/// Matches a splat param, which takes the rest of the path, even when
+/// that's empty.
+pub fn match_splat(path: &str) -> (&str, &str) {
+    (path, "")
+}
+
+/// Splits a `Vec` splat param into its segments. An empty splat has none,
+/// the same as an empty `Vec` is written out, so a `Vec` holding a single
+/// empty segment doesn't survive the round trip.
+pub fn splat_segments(splat: &str) -> impl Iterator<Item = &str> {
+    let count = if splat.is_empty() { 0 } else { usize::MAX };
+
+    splat.split('/').take(count)
+}
+
+/// Matches an optional param at the end of the path, along with the slash
+/// in front of it. The param is present but empty when the path ends with
+/// that slash, the way `Some("")` is written out.
+pub fn match_optional_param(path: &str) -> Option<(Option<&str>, &str)> {
+    if path.is_empty() {
+        return Some((None, path));
+    }
+
+    let rest = match_literal(path, "/")?;
+    let param = current_segment(rest);
+
+    Some((Some(param), &rest[param.len()..]))
+}
+
+/// Turns the named groups of a path regex into plain groups, so the regexes
+/// of an enum's variants can be joined with `|` even when they share param
+/// names.
+pub fn unname_groups(pattern: &str) -> String {
+    let mut unnamed = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+    let mut in_class = false;
+
+    while let Some(c) = chars.next() {
+        unnamed.push(c);
+
+        match c {
+            '\\' => unnamed.extend(chars.next()),
+            '[' => in_class = true,
+            ']' => in_class = false,
+            '(' if !in_class && chars.as_str().starts_with("?P<") => {
+                unnamed.push_str("?:");
+                chars.by_ref().find(|&c| c == '>');
+            }
+            _ => {}
+        }
+    }
+
+    unnamed
+}
+
+#[test]
+fn param_until_segment_end() {
+    assert_eq!(match_param("12/posts", ""), Some(("12", "/posts")));
+    assert_eq!(match_param("12", ""), Some(("12", "")));
+    assert_eq!(match_param("/posts", ""), None);
+    assert_eq!(match_param("", ""), None);
+}
+
+#[test]
+fn param_until_literal() {
+    assert_eq!(match_param("12-my-post", "-"), Some(("12", "-my-post")));
+    assert_eq!(match_param("-12-post", "-"), Some(("-12", "-post")));
+    assert_eq!(match_param("田中.txt", "."), Some(("田中", ".txt")));
+    assert_eq!(match_param("12/my-post", "-"), None);
+    assert_eq!(match_param("12", "-"), None);
+}
+
+#[test]
+fn param_before_suffix() {
+    assert_eq!(
+        match_param_before("a.tar.gz", ".tar.gz"),
+        Some(("a", ".tar.gz"))
+    );
+    assert_eq!(
+        match_param_before("a.tar.gz.tar.gz/x", ".tar.gz"),
+        Some(("a.tar.gz", ".tar.gz/x"))
+    );
+    assert_eq!(match_param_before(".tar.gz", ".tar.gz"), None);
+    assert_eq!(match_param_before("a.tar.gz.zip", ".tar.gz"), None);
+    assert_eq!(match_param_before("a/b.tar.gz", ".tar.gz"), None);
+}
+
+#[test]
+fn optional_param() {
+    assert_eq!(match_optional_param(""), Some((None, "")));
+    assert_eq!(match_optional_param("/3"), Some((Some("3"), "")));
+    assert_eq!(match_optional_param("/3/4"), Some((Some("3"), "/4")));
+    assert_eq!(match_optional_param("/"), Some((Some(""), "")));
+    assert_eq!(match_optional_param("//4"), Some((Some(""), "/4")));
+    assert_eq!(match_optional_param("3"), None);
+}
+
+#[test]
+fn splat_segments_of_empty_splat() {
+    assert_eq!(splat_segments("a//b").collect::<Vec<_>>(), ["a", "", "b"]);
+    assert_eq!(splat_segments("").count(), 0);
+}
+
+#[test]
+fn unnamed_groups() {
+    assert_eq!(
+        unname_groups(r"^/a/(?P<id>[^/]+)$|^/b/(?P<id>[^/(]+)\(?P<x>$"),
+        r"^/a/(?:[^/]+)$|^/b/(?:[^/(]+)\(?P<x>$"
+    );
+    assert_eq!(unname_groups(r"^/c/(?P<n>[(?P<x>])$"), r"^/c/(?:[(?P<x>])$");
+}
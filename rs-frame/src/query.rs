@@ -0,0 +1,631 @@
+//! Query string helpers for `#[derive(AppPath)]`, including writers which
+//! serialize query strings straight into a formatter without building
+//! intermediate `String`s.
+
+use crate::codec::QueryEncoding;
+use crate::encoding::{decode_query_param, EncodeQueryParam};
+use crate::{PathParseErr, QueryCodec, QueryParseErr, QueryWriteErr};
+use percent_encoding::percent_decode;
+use serde::de::{self, DeserializeOwned, Visitor};
+use serde::Serialize;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// Deserializes the query string into a `#[query]` field with its codec.
+pub fn parse_query<C: QueryCodec, T: DeserializeOwned>(
+    query: &str,
+    encoding: QueryEncoding,
+    field: &'static str,
+    pattern: &'static str,
+) -> Result<T, PathParseErr> {
+    C::decode(query, encoding).map_err(|source| {
+        PathParseErr::QueryParseErr(QueryParseErr {
+            field,
+            key: None,
+            query: query.to_string(),
+            pattern,
+            source,
+        })
+    })
+}
+
+/// Finds `key` in the query string and parses its value into a
+/// `#[query_param]` field, or returns `None` if it isn't there. If the key
+/// shows up more than once, the first value wins.
+pub fn parse_query_param<T>(
+    query: Option<&str>,
+    key: &'static str,
+    encoding: QueryEncoding,
+    field: &'static str,
+    pattern: &'static str,
+) -> Result<Option<T>, PathParseErr>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    let query = match query {
+        Some(query) => query,
+        None => return Ok(None),
+    };
+
+    let query_param_err = |source| {
+        PathParseErr::QueryParseErr(QueryParseErr {
+            field,
+            key: Some(key),
+            query: query.to_string(),
+            pattern,
+            source,
+        })
+    };
+
+    for pair in query.split('&') {
+        let (pair_key, value) = split_pair(pair);
+
+        if decode_query_param(pair_key, encoding).map_err(|e| query_param_err(e.into()))? != key {
+            continue;
+        }
+
+        return decode_query_param(value, encoding)
+            .map_err(|e| query_param_err(e.into()))?
+            .parse()
+            .map(Some)
+            .map_err(|e: T::Err| query_param_err(e.to_string().into()));
+    }
+
+    Ok(None)
+}
+
+/// Like `parse_query_param`, for a field which isn't an `Option`.
+pub fn parse_required_query_param<T>(
+    query: Option<&str>,
+    key: &'static str,
+    encoding: QueryEncoding,
+    field: &'static str,
+    pattern: &'static str,
+) -> Result<T, PathParseErr>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    let query_string = query.ok_or(PathParseErr::NoQueryString)?;
+
+    parse_query_param(query, key, encoding, field, pattern)?.ok_or_else(|| {
+        PathParseErr::QueryParseErr(QueryParseErr {
+            field,
+            key: Some(key),
+            query: query_string.to_string(),
+            pattern,
+            source: format!("`{}` is missing", key).into(),
+        })
+    })
+}
+
+/// The top-level keys a `#[query]` field's type reads, found by watching
+/// which fields its `Deserialize` impl asks for. `None` means it takes any
+/// key, like a map or a struct with a `#[serde(flatten)]` field.
+pub fn query_keys<T: DeserializeOwned>() -> Option<&'static [&'static str]> {
+    match T::deserialize(KeysDeserializer) {
+        Err(KeysErr::Fields(fields)) => Some(fields),
+        _ => None,
+    }
+}
+
+/// Whether the query string has a pair for one of `keys`, the keys a query
+/// field reads, compared the same way as in `unknown_query_pairs`. `None`
+/// keys take any pair. A strict `Option` `#[query]` field is only parsed
+/// when this is true, so the keys of other fields don't make it present.
+pub fn query_has_keys(
+    query: &str,
+    keys: Option<&'static [&'static str]>,
+    encoding: QueryEncoding,
+) -> bool {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .any(|pair| {
+            let keys = match keys {
+                Some(keys) => keys,
+                None => return true,
+            };
+
+            let (key, _) = split_pair(pair);
+            let key = decode_query_param(key, encoding).unwrap_or_else(|_| key.into());
+
+            keys.contains(&top_level_key(&key))
+        })
+}
+
+/// Finds the pairs in the query string whose keys aren't in `known_keys`,
+/// the keys of each query field. Keys are compared up to the first `[`, so
+/// `ids[0]` counts as `ids`. A key which shows up more than once has a pair
+/// for each of its values, in the order they came in.
+pub fn unknown_query_pairs(
+    query: Option<&str>,
+    known_keys: &[Option<&'static [&'static str]>],
+    encoding: QueryEncoding,
+) -> Vec<(String, String)> {
+    let mut unknown: Vec<(String, String)> = vec![];
+
+    if known_keys.iter().any(Option::is_none) {
+        return unknown;
+    }
+
+    for pair in query
+        .unwrap_or("")
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+    {
+        let (key, value) = split_pair(pair);
+        let key = decode_query_param(key, encoding).unwrap_or_else(|_| key.into());
+        let is_known = known_keys
+            .iter()
+            .flat_map(|keys| keys.unwrap_or(&[]))
+            .any(|known_key| *known_key == top_level_key(&key));
+
+        if is_known {
+            continue;
+        }
+
+        let value = decode_query_param(value, encoding).unwrap_or_else(|_| value.into());
+        unknown.push((key.into_owned(), value.into_owned()));
+    }
+
+    unknown
+}
+
+/// Fails with `UnknownQueryKeys` if the query string has keys which aren't
+/// in `known_keys`, for `#[query(deny_unknown)]`.
+pub fn deny_unknown_query_keys(
+    query: Option<&str>,
+    known_keys: &[Option<&'static [&'static str]>],
+    encoding: QueryEncoding,
+) -> Result<(), PathParseErr> {
+    let mut unknown_keys: Vec<String> = vec![];
+
+    for (key, _) in unknown_query_pairs(query, known_keys, encoding) {
+        if !unknown_keys.contains(&key) {
+            unknown_keys.push(key);
+        }
+    }
+
+    if unknown_keys.is_empty() {
+        return Ok(());
+    }
+
+    Err(PathParseErr::UnknownQueryKeys(unknown_keys))
+}
+
+/// Writes `prefix` in front of the first non-empty write, and nothing at all
+/// if nothing else gets written. `Display` uses it for the `?` in front of
+/// the query string.
+pub struct LazyPrefix<'a, W> {
+    inner: &'a mut W,
+    prefix: &'static str,
+    written: bool,
+}
+
+impl<'a, W: fmt::Write> LazyPrefix<'a, W> {
+    pub fn new(inner: &'a mut W, prefix: &'static str) -> Self {
+        LazyPrefix {
+            inner,
+            prefix,
+            written: false,
+        }
+    }
+}
+
+impl<'a, W: fmt::Write> fmt::Write for LazyPrefix<'a, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if s.is_empty() {
+            return Ok(());
+        }
+
+        if !self.written {
+            self.written = true;
+            self.inner.write_str(self.prefix)?;
+        }
+
+        self.inner.write_str(s)
+    }
+}
+
+/// Joins the serialized `#[query]` and `#[query_param]` fields of a path
+/// with `&`, skipping the ones which serialize to nothing.
+///
+/// When a path has more than one query field, the writer is created with
+/// `merging` so the fields share one key space: a key is only written the
+/// first time, and writing it again with a different value is a `Conflict`,
+/// unless the writer was told to `keep_first`.
+///
+/// A `canonical` writer merges too, and also holds on to the pairs until
+/// `finish` so it can sort them by key. It drops the empty values of a
+/// `#[query]` field, and the values its type reads from an empty query
+/// string, like `friends_only=false` for a `#[serde(default)] friends_only:
+/// bool`, but only when the field reads the same value back without them.
+pub struct QueryWriter<'a, W> {
+    inner: &'a mut W,
+    written: bool,
+    /// The decoded keys written so far along with their values, kept only
+    /// when merging
+    pairs: Option<Vec<(String, Vec<String>)>>,
+    /// The decoded keys and encoded pairs waiting to be sorted, kept only
+    /// when canonical
+    unsorted: Option<Vec<(String, String)>>,
+    /// Whether a key written again with a different value is skipped
+    /// instead of being a `Conflict`
+    keep_first: bool,
+}
+
+impl<'a, W: fmt::Write> QueryWriter<'a, W> {
+    pub fn new(inner: &'a mut W) -> Self {
+        QueryWriter {
+            inner,
+            written: false,
+            pairs: None,
+            unsorted: None,
+            keep_first: false,
+        }
+    }
+
+    pub fn merging(inner: &'a mut W) -> Self {
+        QueryWriter {
+            inner,
+            written: false,
+            pairs: Some(vec![]),
+            unsorted: None,
+            keep_first: false,
+        }
+    }
+
+    pub fn canonical(inner: &'a mut W) -> Self {
+        QueryWriter {
+            inner,
+            written: false,
+            pairs: Some(vec![]),
+            unsorted: Some(vec![]),
+            keep_first: false,
+        }
+    }
+
+    /// Keeps the value of whichever field writes a key first, skipping the
+    /// values which disagree with it instead of returning a `Conflict`.
+    /// `Display` writes the query string this way, since it can't fail.
+    pub fn keep_first(mut self) -> Self {
+        self.keep_first = true;
+        self
+    }
+
+    pub fn write_part<C: QueryCodec, T: Serialize + DeserializeOwned>(
+        &mut self,
+        part: &T,
+        encoding: QueryEncoding,
+    ) -> Result<(), QueryWriteErr> {
+        if self.pairs.is_some() {
+            let mut serialized = String::new();
+            C::encode(part, encoding, &mut serialized)?;
+
+            if self.unsorted.is_some() {
+                let trimmed = trim_defaults(&serialized, &default_query::<C, T>(encoding));
+
+                // Only if reading what's left gives back the same value, so
+                // canonical URLs parse into the path they came from
+                let same_value = C::decode::<T>(&trimmed, encoding)
+                    .ok()
+                    .and_then(|value| {
+                        let mut reserialized = String::new();
+                        C::encode(&value, encoding, &mut reserialized).ok()?;
+                        Some(reserialized)
+                    })
+                    .is_some_and(|reserialized| reserialized == serialized);
+
+                if same_value {
+                    return self.write_pairs(&trimmed);
+                }
+            }
+
+            return self.write_pairs(&serialized);
+        }
+
+        let separator = if self.written { "&" } else { "" };
+        let mut writer = LazyPrefix::new(self.inner, separator);
+
+        C::encode(part, encoding, &mut writer)?;
+
+        self.written |= writer.written;
+
+        Ok(())
+    }
+
+    pub fn write_param<T: fmt::Display>(
+        &mut self,
+        key: &str,
+        value: &T,
+        encoding: QueryEncoding,
+    ) -> Result<(), QueryWriteErr> {
+        if self.pairs.is_some() {
+            let pair = format!(
+                "{}={}",
+                EncodeQueryParam(key, encoding),
+                EncodeQueryParam(value, encoding)
+            );
+            return self.write_pairs(&pair);
+        }
+
+        if self.written {
+            self.inner.write_str("&")?;
+        }
+
+        self.written = true;
+
+        write!(
+            self.inner,
+            "{}={}",
+            EncodeQueryParam(key, encoding),
+            EncodeQueryParam(value, encoding)
+        )?;
+
+        Ok(())
+    }
+
+    /// Writes the `key=value` pairs of a `rest` field together, so a key
+    /// which shows up more than once among them keeps all of its values.
+    pub fn write_params<K: fmt::Display, V: fmt::Display>(
+        &mut self,
+        params: impl IntoIterator<Item = (K, V)>,
+        encoding: QueryEncoding,
+    ) -> Result<(), QueryWriteErr> {
+        let mut query = String::new();
+
+        for (key, value) in params {
+            if !query.is_empty() {
+                query.push('&');
+            }
+
+            fmt::Write::write_fmt(
+                &mut query,
+                format_args!(
+                    "{}={}",
+                    EncodeQueryParam(&key, encoding),
+                    EncodeQueryParam(&value, encoding)
+                ),
+            )?;
+        }
+
+        self.write_encoded(&query)
+    }
+
+    /// Writes a query string which is already encoded, like the output of
+    /// another path's `write_query`.
+    pub fn write_encoded(&mut self, query: &str) -> Result<(), QueryWriteErr> {
+        if self.pairs.is_some() {
+            return self.write_pairs(query);
+        }
+
+        let separator = if self.written { "&" } else { "" };
+        let mut writer = LazyPrefix::new(self.inner, separator);
+
+        fmt::Write::write_str(&mut writer, query)?;
+
+        self.written |= writer.written;
+
+        Ok(())
+    }
+
+    /// Writes the sorted pairs of a canonical writer, and does nothing for
+    /// the others.
+    pub fn finish(mut self) -> Result<(), QueryWriteErr> {
+        let mut unsorted = match self.unsorted.take() {
+            Some(unsorted) => unsorted,
+            None => return Ok(()),
+        };
+
+        // A stable sort, so the values of a repeated key stay in order
+        unsorted.sort_by(|(key, _), (other_key, _)| key.cmp(other_key));
+
+        for (i, (_, pair)) in unsorted.iter().enumerate() {
+            if i > 0 {
+                self.inner.write_str("&")?;
+            }
+
+            self.inner.write_str(pair)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the encoded `key=value` pairs in `query` whose keys haven't
+    /// been written yet. A key repeated within `query`, like `id=1&id=2`, is
+    /// compared with what was written before as a whole.
+    fn write_pairs(&mut self, query: &str) -> Result<(), QueryWriteErr> {
+        let keys = group_pairs(query);
+        let keep_first = self.keep_first;
+        let pairs = self
+            .pairs
+            .as_mut()
+            .expect("only merging writers keep pairs");
+
+        for (key, values, encoded) in keys {
+            match pairs.iter().find(|(seen_key, _)| *seen_key == key) {
+                Some((_, seen_values)) if *seen_values == values || keep_first => continue,
+                Some((_, seen_values)) => {
+                    return Err(QueryWriteErr::Conflict {
+                        key,
+                        first: seen_values.join(","),
+                        second: values.join(","),
+                    });
+                }
+                None => pairs.push((key.clone(), values)),
+            }
+
+            if let Some(ref mut unsorted) = self.unsorted {
+                unsorted.extend(
+                    encoded
+                        .into_iter()
+                        .map(|pair| (key.clone(), pair.to_string())),
+                );
+                continue;
+            }
+
+            for pair in encoded {
+                if self.written {
+                    self.inner.write_str("&")?;
+                }
+
+                self.written = true;
+                self.inner.write_str(pair)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Groups the encoded pairs in `query` by their decoded key, along with
+/// their decoded values
+fn group_pairs(query: &str) -> Vec<(String, Vec<String>, Vec<&str>)> {
+    let mut keys: Vec<(String, Vec<String>, Vec<&str>)> = vec![];
+
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (key, value) = split_pair(pair);
+        let key = decode_query_component(key);
+        let value = decode_query_component(value);
+
+        match keys.iter_mut().find(|(seen_key, _, _)| *seen_key == key) {
+            Some((_, values, encoded)) => {
+                values.push(value);
+                encoded.push(pair);
+            }
+            None => keys.push((key, vec![value], vec![pair])),
+        }
+    }
+
+    keys
+}
+
+/// The pairs of `query` without its empty values, and without the keys whose
+/// values are all the same as in `defaults`
+fn trim_defaults(query: &str, defaults: &str) -> String {
+    let defaults = group_pairs(defaults);
+    let mut trimmed = String::new();
+
+    for (key, values, encoded) in group_pairs(query) {
+        let is_default = defaults.iter().any(|(default_key, default_values, _)| {
+            *default_key == key && *default_values == values
+        });
+
+        if is_default {
+            continue;
+        }
+
+        for (value, pair) in values.iter().zip(encoded) {
+            if value.is_empty() {
+                continue;
+            }
+
+            if !trimmed.is_empty() {
+                trimmed.push('&');
+            }
+
+            trimmed.push_str(pair);
+        }
+    }
+
+    trimmed
+}
+
+/// What a `#[query]` field's type writes when it's read from an empty query
+/// string, or nothing if it can't be
+fn default_query<C: QueryCodec, T: Serialize + DeserializeOwned>(
+    encoding: QueryEncoding,
+) -> String {
+    let mut serialized = String::new();
+
+    if let Ok(default) = C::decode::<T>("", encoding) {
+        if C::encode(&default, encoding, &mut serialized).is_err() {
+            serialized.clear();
+        }
+    }
+
+    serialized
+}
+
+/// The part of a key up to the first `[`, so `ids[0]` counts as `ids`
+fn top_level_key(key: &str) -> &str {
+    key.split('[').next().unwrap_or("")
+}
+
+fn split_pair(pair: &str) -> (&str, &str) {
+    match pair.find('=') {
+        Some(equals_pos) => (&pair[..equals_pos], &pair[(equals_pos + 1)..]),
+        None => (pair, ""),
+    }
+}
+
+/// Decodes a key or value written by a codec or `write_param`, none of
+/// which leave a literal `+` unencoded, whatever their `QueryEncoding`
+fn decode_query_component(component: &str) -> String {
+    percent_decode(component.replace('+', " ").as_bytes())
+        .decode_utf8_lossy()
+        .into_owned()
+}
+
+/// Stops deserializing as soon as a struct says which fields it has, for
+/// `query_keys`
+struct KeysDeserializer;
+
+#[derive(Debug)]
+enum KeysErr {
+    Fields(&'static [&'static str]),
+    /// The type isn't a plain struct, so it could read any key
+    AnyKeys,
+}
+
+impl fmt::Display for KeysErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl Error for KeysErr {}
+
+impl de::Error for KeysErr {
+    fn custom<T: fmt::Display>(_msg: T) -> Self {
+        KeysErr::AnyKeys
+    }
+}
+
+impl<'de> de::Deserializer<'de> for KeysDeserializer {
+    type Error = KeysErr;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, KeysErr> {
+        Err(KeysErr::AnyKeys)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, KeysErr> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, KeysErr> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, KeysErr> {
+        Err(KeysErr::Fields(fields))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map enum
+        identifier ignored_any
+    }
+}
@@ -0,0 +1,649 @@
+//! A router which resolves a URL against many `AppPath` types at once.
+
+use crate::{AppPath, PathParseErr};
+use regex::Regex;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Resolves URLs against a set of registered `AppPath` types.
+///
+/// The templates of every route are kept in a trie keyed by path segment, so
+/// finding the candidates for a URL takes one lookup per segment no matter how
+/// many routes there are. Each candidate's `FromStr` then has the final say,
+/// which takes care of constraints, param types and the query string.
+///
+/// When more than one route fits a URL, static segments win over params and
+/// params win over splats, deciding segment by segment from the left. Routes
+/// which are still tied are tried in the order they were added, and an enum
+/// decides between its own variants in declaration order.
+pub struct Router<T> {
+    root: Node,
+    routes: Vec<Route<T>>,
+}
+
+type Parser<T> = Box<dyn Fn(&str) -> Result<T, PathParseErr>>;
+
+struct Route<T> {
+    templates: Vec<String>,
+    /// The regex of each template, to tell which ones a URL's path matches
+    /// without going through `parse`
+    patterns: Vec<String>,
+    parse: Parser<T>,
+}
+
+/// Two routes which both match the same URL, as found by `Router::conflicts`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RouteConflict {
+    /// The template of the route the router tries first for `example`
+    pub winner: String,
+    /// The template of the route which is shadowed for `example`
+    pub loser: String,
+    /// A URL which both templates match
+    pub example: String,
+}
+
+impl fmt::Display for RouteConflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "`{}` matches both `{}` and `{}`, `{}` wins",
+            self.example, self.winner, self.loser, self.winner
+        )
+    }
+}
+
+impl<T> Router<T> {
+    pub fn new() -> Router<T> {
+        Router {
+            root: Node::default(),
+            routes: vec![],
+        }
+    }
+
+    /// Adds a route for `P`, which calls `handler` with the parsed path when
+    /// it's the one that matches.
+    pub fn add<P, F>(&mut self, handler: F)
+    where
+        P: AppPath + FromStr<Err = PathParseErr> + 'static,
+        F: Fn(P) -> T + 'static,
+    {
+        let route = self.routes.len();
+        let templates = P::path_templates();
+
+        for template in &templates {
+            self.root.insert(&template_segments(template), route);
+        }
+
+        self.routes.push(Route {
+            templates,
+            patterns: P::path_patterns(),
+            parse: Box::new(move |app_path| app_path.parse().map(&handler)),
+        });
+    }
+
+    /// Adds a route for `P`, which is converted into `T` when it matches.
+    pub fn add_path<P>(&mut self)
+    where
+        P: AppPath + FromStr<Err = PathParseErr> + Into<T> + 'static,
+        T: 'static,
+    {
+        self.add(P::into)
+    }
+
+    /// Parses `app_path` with the highest priority route that accepts it.
+    /// If none do, the error from the first route whose template fit is
+    /// returned, or `NoMatches` if none fit at all. A `#fragment` on the end
+    /// is ignored.
+    pub fn route(&self, app_path: &str) -> Result<T, PathParseErr> {
+        let app_path = &app_path[..app_path.find('#').unwrap_or(app_path.len())];
+        let just_path = &app_path[..app_path.find('?').unwrap_or(app_path.len())];
+        let mut result = Err(PathParseErr::NoMatches);
+
+        if let Some(rest) = just_path.strip_prefix('/') {
+            self.root.find(
+                Some(rest),
+                &mut |route| match (self.routes[route].parse)(app_path) {
+                    Ok(value) => {
+                        result = Ok(value);
+                        true
+                    }
+                    Err(PathParseErr::NoMatches) => false,
+                    Err(e) => {
+                        if let Err(PathParseErr::NoMatches) = result {
+                            result = Err(e);
+                        }
+
+                        false
+                    }
+                },
+            );
+        }
+
+        result
+    }
+
+    /// Finds every pair of templates with a URL they both match, along with
+    /// the template the router tries first for it. A route table without
+    /// conflicts can be checked in a test with
+    /// `assert_eq!(router.conflicts(), vec![])`.
+    ///
+    /// Conflicts are found by trying URLs built from both templates with
+    /// sample values like `1` and `a` for the params, so an overlap which only
+    /// shows up for unusual values can be missed. Whether a URL matches only
+    /// goes by each template's regex, not by how its values parse.
+    pub fn conflicts(&self) -> Vec<RouteConflict> {
+        let templates: Vec<(usize, &String, Regex)> = self
+            .routes
+            .iter()
+            .enumerate()
+            .flat_map(
+                |(
+                    route,
+                    Route {
+                        templates,
+                        patterns,
+                        ..
+                    },
+                )| {
+                    templates
+                        .iter()
+                        .zip(patterns)
+                        .map(move |(template, pattern)| {
+                            let regex = Regex::new(pattern).expect("Failed to compile regex");
+                            (route, template, regex)
+                        })
+                },
+            )
+            .collect();
+
+        let mut conflicts = vec![];
+
+        for (i, (a, template_a, regex_a)) in templates.iter().enumerate() {
+            for (b, template_b, regex_b) in &templates[(i + 1)..] {
+                let example = example_urls(template_a, template_b)
+                    .into_iter()
+                    .find(|url| regex_a.is_match(url) && regex_b.is_match(url));
+
+                if let Some(example) = example {
+                    // An enum tries its own variants in the order they're declared
+                    let (winner, loser) = if a == b || self.first_route(&example, *a, *b) == *a {
+                        (template_a, template_b)
+                    } else {
+                        (template_b, template_a)
+                    };
+
+                    conflicts.push(RouteConflict {
+                        winner: winner.to_string(),
+                        loser: loser.to_string(),
+                        example,
+                    });
+                }
+            }
+        }
+
+        conflicts
+    }
+
+    /// Returns whichever of `a` and `b` the trie offers first for `app_path`
+    fn first_route(&self, app_path: &str, a: usize, b: usize) -> usize {
+        let mut first = a;
+
+        self.root.find(Some(&app_path[1..]), &mut |route| {
+            if route == a || route == b {
+                first = route;
+                true
+            } else {
+                false
+            }
+        });
+
+        first
+    }
+}
+
+impl<T> Default for Router<T> {
+    fn default() -> Router<T> {
+        Router::new()
+    }
+}
+
+#[derive(Default)]
+struct Node {
+    statics: HashMap<String, Node>,
+    param: Option<Box<Node>>,
+    /// Routes whose template ends at this node
+    routes: Vec<usize>,
+    /// Routes whose splat takes the rest of the path from this node
+    splats: Vec<usize>,
+}
+
+impl Node {
+    fn insert(&mut self, segments: &[Segment], route: usize) {
+        match segments.split_first() {
+            None => {
+                if !self.routes.contains(&route) {
+                    self.routes.push(route);
+                }
+            }
+            Some((Segment::Static(text), rest)) => self
+                .statics
+                .entry(text.to_string())
+                .or_default()
+                .insert(rest, route),
+            Some((Segment::Param(_), rest)) => self
+                .param
+                .get_or_insert_with(Box::default)
+                .insert(rest, route),
+            Some((Segment::OptionalParam(_), rest)) => {
+                self.insert(rest, route);
+                self.param
+                    .get_or_insert_with(Box::default)
+                    .insert(rest, route);
+            }
+            Some((Segment::Splat(_), _)) => {
+                if !self.splats.contains(&route) {
+                    self.splats.push(route);
+                }
+            }
+        }
+    }
+
+    /// Offers each route which fits `rest` to `try_route` in priority order,
+    /// stopping as soon as it returns true. `rest` is the path after the next
+    /// slash, or `None` when the path has run out.
+    fn find<F: FnMut(usize) -> bool>(&self, rest: Option<&str>, try_route: &mut F) -> bool {
+        let rest = match rest {
+            Some(rest) => rest,
+            None => return self.routes.iter().any(|&route| try_route(route)),
+        };
+
+        let (segment, next) = match rest.find('/') {
+            Some(slash_pos) => (&rest[..slash_pos], Some(&rest[(slash_pos + 1)..])),
+            None => (rest, None),
+        };
+
+        if let Some(child) = self.statics.get(segment) {
+            if child.find(next, try_route) {
+                return true;
+            }
+        }
+
+        // Optional params and splats can be empty, so empty segments are left
+        // for `FromStr` to turn down
+        if let Some(ref child) = self.param {
+            if child.find(next, try_route) {
+                return true;
+            }
+        }
+
+        self.splats.iter().any(|&route| try_route(route))
+    }
+}
+
+/// A segment of a template, with the params holding their text to build
+/// example URLs from.
+#[derive(Clone, Debug, PartialEq)]
+enum Segment<'a> {
+    Static(&'a str),
+    Param(&'a str),
+    OptionalParam(&'a str),
+    Splat(&'a str),
+}
+
+/// Splits a `#[path]` template into segments, ignoring anything inside a
+/// param's constraint. Everything from a splat onwards is one `Splat`, and so
+/// is everything from a segment whose constraint has a `/` in it, since the
+/// param can take up more than one segment of the URL.
+fn template_segments(template: &str) -> Vec<Segment<'_>> {
+    let mut segments = vec![];
+    let mut start = 1;
+    let mut depth = 0;
+    let mut escaped = false;
+    let mut has_param = false;
+    // Whether the last characters were a param's name, which is the only
+    // place a `(` starts a constraint rather than being static text
+    let mut in_name = false;
+
+    for (i, c) in template.char_indices().skip(1) {
+        if depth > 0 {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                '/' => {
+                    segments.push(Segment::Splat(&template[start..]));
+                    return segments;
+                }
+                _ => {}
+            }
+
+            continue;
+        }
+
+        let after_name = in_name;
+        in_name = in_name && (c.is_ascii_alphanumeric() || c == '_');
+
+        match c {
+            '(' if after_name => depth += 1,
+            ':' => {
+                has_param = true;
+                in_name = true;
+            }
+            '*' => {
+                segments.push(Segment::Splat(&template[start..]));
+                return segments;
+            }
+            '/' => {
+                segments.push(segment(&template[start..i], has_param));
+                start = i + 1;
+                has_param = false;
+            }
+            _ => {}
+        }
+    }
+
+    segments.push(segment(&template[start..], has_param));
+    segments
+}
+
+fn segment(text: &str, has_param: bool) -> Segment<'_> {
+    if !has_param {
+        Segment::Static(text)
+    } else if text.starts_with(':') && text.ends_with('?') {
+        Segment::OptionalParam(text)
+    } else {
+        Segment::Param(text)
+    }
+}
+
+/// The values tried for params when looking for conflicts
+const SAMPLE_VALUES: [&str; 2] = ["1", "a"];
+
+/// Fills in the params and splats of some template text with `value`
+fn sample_text(text: &str, value: &str) -> String {
+    let mut sample = String::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != ':' && c != '*' {
+            sample.push(c);
+            continue;
+        }
+
+        while chars
+            .peek()
+            .is_some_and(|c| c.is_ascii_alphanumeric() || *c == '_')
+        {
+            chars.next();
+        }
+
+        if chars.peek() == Some(&'(') {
+            let mut depth = 0;
+            let mut escaped = false;
+
+            for c in &mut chars {
+                match c {
+                    _ if escaped => escaped = false,
+                    '\\' => escaped = true,
+                    '(' => depth += 1,
+                    ')' => depth -= 1,
+                    _ => {}
+                }
+
+                if depth == 0 {
+                    break;
+                }
+            }
+        }
+
+        if chars.peek() == Some(&'?') {
+            chars.next();
+        }
+
+        sample.push_str(value);
+    }
+
+    sample
+}
+
+fn segment_samples(segment: &Segment) -> Vec<String> {
+    match *segment {
+        Segment::Static(text) => vec![text.to_string()],
+        Segment::Param(text) | Segment::OptionalParam(text) | Segment::Splat(text) => SAMPLE_VALUES
+            .iter()
+            .map(|value| sample_text(text, value))
+            .collect(),
+    }
+}
+
+fn segment_fits(segment: &Segment, text: &str) -> bool {
+    match *segment {
+        Segment::Static(static_text) => static_text == text,
+        _ => !text.is_empty() && !text.contains('/'),
+    }
+}
+
+/// Every version of `segments` with its optional params either left out or
+/// turned into regular params
+fn expand_optionals<'a>(segments: &[Segment<'a>]) -> Vec<Vec<Segment<'a>>> {
+    let (first, rest) = match segments.split_first() {
+        Some(split) => split,
+        None => return vec![vec![]],
+    };
+
+    let mut expanded = vec![];
+
+    for rest in expand_optionals(rest) {
+        if let Segment::OptionalParam(text) = *first {
+            expanded.push(rest.clone());
+
+            let mut with_param = vec![Segment::Param(text)];
+            with_param.extend(rest);
+            expanded.push(with_param);
+        } else {
+            let mut with_first = vec![first.clone()];
+            with_first.extend(rest);
+            expanded.push(with_first);
+        }
+    }
+
+    expanded
+}
+
+/// The sample paths, as lists of segments, for a template on its own
+fn samples(segments: &[Segment]) -> Vec<Vec<String>> {
+    let (first, rest) = match segments.split_first() {
+        Some(split) => split,
+        None => return vec![vec![]],
+    };
+
+    let rest_samples = samples(rest);
+    let mut paths = vec![];
+
+    for sample in segment_samples(first) {
+        for rest in &rest_samples {
+            let mut path = vec![sample.clone()];
+            path.extend(rest.iter().cloned());
+            paths.push(path);
+        }
+    }
+
+    paths
+}
+
+/// Candidate paths, as lists of segments, which could fit both `a` and `b`
+fn example_segments(a: &[Segment], b: &[Segment]) -> Vec<Vec<String>> {
+    match (a.split_first(), b.split_first()) {
+        (None, None) => vec![vec![]],
+        (Some((Segment::Splat(_), _)), Some((Segment::Splat(_), _))) => {
+            let mut paths = samples(a);
+            paths.extend(samples(b));
+            paths
+        }
+        (Some((Segment::Splat(_), _)), Some(_)) => samples(b),
+        (Some(_), Some((Segment::Splat(_), _))) => samples(a),
+        (Some((first_a, rest_a)), Some((first_b, rest_b))) => {
+            let mut candidates = segment_samples(first_a);
+            candidates.extend(segment_samples(first_b));
+            candidates.dedup();
+
+            let rest_paths = example_segments(rest_a, rest_b);
+            let mut paths = vec![];
+
+            for candidate in candidates {
+                if !segment_fits(first_a, &candidate) || !segment_fits(first_b, &candidate) {
+                    continue;
+                }
+
+                for rest in &rest_paths {
+                    let mut path = vec![candidate.clone()];
+                    path.extend(rest.iter().cloned());
+                    paths.push(path);
+                }
+            }
+
+            paths
+        }
+        _ => vec![],
+    }
+}
+
+/// URLs which might match both templates, to be checked against the routes
+fn example_urls(a: &str, b: &str) -> Vec<String> {
+    let mut urls = vec![];
+
+    for a in expand_optionals(&template_segments(a)) {
+        for b in expand_optionals(&template_segments(b)) {
+            for segments in example_segments(&a, &b) {
+                let url = format!("/{}", segments.join("/"));
+
+                if !urls.contains(&url) {
+                    urls.push(url);
+                }
+            }
+        }
+    }
+
+    urls
+}
+
+#[test]
+fn template_segments_static() {
+    assert_eq!(template_segments("/"), vec![Segment::Static("")]);
+    assert_eq!(
+        template_segments("/users/new"),
+        vec![Segment::Static("users"), Segment::Static("new")]
+    );
+}
+
+#[test]
+fn template_segments_params() {
+    assert_eq!(
+        template_segments("/users/:user_id/posts/:page?"),
+        vec![
+            Segment::Static("users"),
+            Segment::Param(":user_id"),
+            Segment::Static("posts"),
+            Segment::OptionalParam(":page?"),
+        ]
+    );
+    assert_eq!(
+        template_segments("/posts/:id-:slug/v:version"),
+        vec![
+            Segment::Static("posts"),
+            Segment::Param(":id-:slug"),
+            Segment::Param("v:version"),
+        ]
+    );
+}
+
+#[test]
+fn template_segments_splat() {
+    assert_eq!(
+        template_segments("/files/:user_id/*file_path"),
+        vec![
+            Segment::Static("files"),
+            Segment::Param(":user_id"),
+            Segment::Splat("*file_path"),
+        ]
+    );
+    assert_eq!(
+        template_segments("/files/v*rest.json"),
+        vec![Segment::Static("files"), Segment::Splat("v*rest.json")]
+    );
+}
+
+#[test]
+fn template_segments_skip_constraints() {
+    assert_eq!(
+        template_segments("/dates/:date(\\d+)/:rest(a*|\\(b\\))"),
+        vec![
+            Segment::Static("dates"),
+            Segment::Param(":date(\\d+)"),
+            Segment::Param(":rest(a*|\\(b\\))"),
+        ]
+    );
+}
+
+#[test]
+fn template_segments_static_parens() {
+    assert_eq!(
+        template_segments("/docs/(draft)/:id"),
+        vec![
+            Segment::Static("docs"),
+            Segment::Static("(draft)"),
+            Segment::Param(":id"),
+        ]
+    );
+    assert_eq!(
+        template_segments("/docs/:id-(a/b)"),
+        vec![
+            Segment::Static("docs"),
+            Segment::Param(":id-(a"),
+            Segment::Static("b)"),
+        ]
+    );
+}
+
+#[test]
+fn template_segments_constraint_with_slash() {
+    assert_eq!(
+        template_segments("/dates/:date(\\d+/\\d+)/:rest(a*|\\(b\\))"),
+        vec![
+            Segment::Static("dates"),
+            Segment::Splat(":date(\\d+/\\d+)/:rest(a*|\\(b\\))"),
+        ]
+    );
+}
+
+#[test]
+fn sample_text_fills_params() {
+    assert_eq!(sample_text(":user_id", "1"), "1");
+    assert_eq!(sample_text(":id-:slug", "a"), "a-a");
+    assert_eq!(sample_text(":id(\\d+)?", "1"), "1");
+    assert_eq!(sample_text(":rest(a*|\\(b\\)).json", "1"), "1.json");
+    assert_eq!(sample_text("v*rest.json", "a"), "va.json");
+}
+
+#[test]
+fn example_urls_fit_both_templates() {
+    assert_eq!(
+        example_urls("/users/:user_id", "/users/new"),
+        vec!["/users/new"]
+    );
+    assert_eq!(
+        example_urls("/users/:user_id", "/users/:name"),
+        vec!["/users/1", "/users/a"]
+    );
+    assert_eq!(
+        example_urls("/users/:user_id/posts/:page?", "/users/new/posts"),
+        vec!["/users/new/posts"]
+    );
+    assert_eq!(
+        example_urls("/files/*path", "/files/:name/info"),
+        vec!["/files/1/info", "/files/a/info"]
+    );
+    assert_eq!(example_urls("/users", "/users/new"), Vec::<String>::new());
+}
@@ -0,0 +1,509 @@
+use rs_frame::{
+    App, AppPath, Controller, Lifecycle, LifecycleCall, PathController, PathParseErr,
+    QueryWriteErr, RecordingHandler, RouteParams, Transition,
+};
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+use std::str::FromStr;
+
+type Log = Rc<RefCell<Vec<String>>>;
+
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/users/:user_id")]
+struct UserDetailPath {
+    user_id: u32,
+}
+
+struct UserDetailController {
+    user_id: u32,
+    log: Log,
+}
+
+impl Hash for UserDetailController {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.user_id.hash(state);
+    }
+}
+
+impl PathController for UserDetailController {
+    type Path = UserDetailPath;
+    type Effect = String;
+
+    fn params(&mut self, path: &UserDetailPath) {
+        self.user_id = path.user_id;
+    }
+
+    fn start(&self) -> Vec<String> {
+        self.log
+            .borrow_mut()
+            .push(format!("start {}", self.user_id));
+        vec![]
+    }
+
+    fn stop(&self) -> Vec<String> {
+        self.log.borrow_mut().push("stop".to_string());
+        vec![]
+    }
+}
+
+#[test]
+fn path_controller_lifecycle() {
+    let log = Log::default();
+    let mut app = App::new();
+    app.add_path_controller(UserDetailController {
+        user_id: 0,
+        log: log.clone(),
+    });
+
+    app.new_route("/users/1".to_string());
+    app.new_route("/users/1".to_string());
+    app.new_route("/users/2".to_string());
+    app.new_route("/users/not-a-number".to_string());
+    app.new_route("/home".to_string());
+
+    assert_eq!(*log.borrow(), vec!["start 1", "stop", "start 2", "stop"]);
+}
+
+/// Emits what it's doing instead of logging it
+#[derive(Default, Hash)]
+struct EmittingController {
+    user_id: u32,
+}
+
+impl PathController for EmittingController {
+    type Path = UserDetailPath;
+    type Effect = String;
+
+    fn params(&mut self, path: &UserDetailPath) {
+        self.user_id = path.user_id;
+    }
+
+    fn start(&self) -> Vec<String> {
+        vec![format!("load user {}", self.user_id)]
+    }
+
+    fn stop(&self) -> Vec<String> {
+        vec!["unload user".to_string(), "clear cache".to_string()]
+    }
+}
+
+#[derive(Hash)]
+struct HomeController;
+
+impl Controller for HomeController {
+    type Effect = String;
+
+    fn params(&mut self, params: &RouteParams) -> Option<()> {
+        if params.data.name == "/home" {
+            Some(())
+        } else {
+            None
+        }
+    }
+
+    fn start(&self) -> Vec<String> {
+        vec!["load home".to_string()]
+    }
+}
+
+fn call(controller: usize, call: Lifecycle, emitted: &[&str]) -> LifecycleCall<String> {
+    LifecycleCall {
+        controller,
+        call,
+        emitted: emitted.iter().map(|command| command.to_string()).collect(),
+    }
+}
+
+#[test]
+fn new_route_returns_transition() {
+    let mut app = App::new();
+    app.add_path_controller(EmittingController::default());
+    app.add_controller(HomeController);
+
+    assert_eq!(
+        app.new_route("/users/1".to_string()).calls,
+        vec![call(0, Lifecycle::Start, &["load user 1"])]
+    );
+
+    assert_eq!(app.new_route("/users/1".to_string()), Transition::default());
+
+    let transition = app.new_route("/users/2".to_string());
+    assert_eq!(
+        transition.calls,
+        vec![
+            call(0, Lifecycle::Stop, &["unload user", "clear cache"]),
+            call(0, Lifecycle::Start, &["load user 2"]),
+        ]
+    );
+    assert_eq!(
+        transition.effects().collect::<Vec<_>>(),
+        vec!["unload user", "clear cache", "load user 2"]
+    );
+
+    let transition = app.new_route("/home".to_string());
+    assert_eq!(
+        transition.calls,
+        vec![
+            call(0, Lifecycle::Stop, &["unload user", "clear cache"]),
+            call(1, Lifecycle::Start, &["load home"]),
+        ]
+    );
+    assert_eq!(
+        transition.for_controller(1).collect::<Vec<_>>(),
+        vec![&call(1, Lifecycle::Start, &["load home"])]
+    );
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum UserEffect {
+    Load(u32),
+    Unload,
+}
+
+#[derive(Default, Hash)]
+struct TypedUserController {
+    user_id: u32,
+}
+
+impl PathController for TypedUserController {
+    type Path = UserDetailPath;
+    type Effect = UserEffect;
+
+    fn params(&mut self, path: &UserDetailPath) {
+        self.user_id = path.user_id;
+    }
+
+    fn start(&self) -> Vec<UserEffect> {
+        vec![UserEffect::Load(self.user_id)]
+    }
+
+    fn stop(&self) -> Vec<UserEffect> {
+        vec![UserEffect::Unload]
+    }
+}
+
+#[test]
+fn effects_go_to_effect_handler() {
+    let recorder = RecordingHandler::new();
+    let mut app = App::new();
+    app.add_path_controller(TypedUserController::default());
+    app.set_effect_handler(recorder.clone());
+
+    let transition = app.new_route("/users/1".to_string());
+    assert_eq!(recorder.take(), vec![UserEffect::Load(1)]);
+    assert_eq!(
+        transition.effects().collect::<Vec<_>>(),
+        vec![&UserEffect::Load(1)]
+    );
+
+    app.new_route("/users/1".to_string());
+    app.new_route("/users/2".to_string());
+    app.new_route("/home".to_string());
+    assert_eq!(
+        recorder.take(),
+        vec![UserEffect::Unload, UserEffect::Load(2), UserEffect::Unload]
+    );
+    assert_eq!(recorder.take(), vec![]);
+}
+
+thread_local! {
+    static PARSES: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Counts how many times it gets parsed
+#[derive(Debug)]
+struct CountedPath;
+
+impl fmt::Display for CountedPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "/counted")
+    }
+}
+
+impl FromStr for CountedPath {
+    type Err = PathParseErr;
+
+    fn from_str(app_path: &str) -> Result<Self, PathParseErr> {
+        PARSES.with(|parses| parses.set(parses.get() + 1));
+
+        if app_path == "/counted" {
+            Ok(CountedPath)
+        } else {
+            Err(PathParseErr::NoMatches)
+        }
+    }
+}
+
+impl AppPath for CountedPath {
+    fn path_pattern() -> String {
+        "^/counted$".to_string()
+    }
+
+    fn path_templates() -> Vec<String> {
+        vec!["/counted".to_string()]
+    }
+
+    fn write_query(&self, _w: &mut impl fmt::Write) -> Result<(), QueryWriteErr> {
+        Ok(())
+    }
+}
+
+#[derive(Hash)]
+struct CountedController;
+
+impl PathController for CountedController {
+    type Path = CountedPath;
+    type Effect = String;
+
+    fn params(&mut self, _path: &CountedPath) {}
+
+    fn start(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+#[derive(Hash)]
+struct UntypedCountedController;
+
+impl Controller for UntypedCountedController {
+    type Effect = String;
+
+    fn params(&mut self, params: &RouteParams) -> Option<()> {
+        params.path::<CountedPath>().map(|_| ())
+    }
+
+    fn start(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+#[test]
+fn path_parsed_once_per_route() {
+    let mut app = App::new();
+    app.add_path_controller(CountedController);
+    app.add_path_controller(CountedController);
+    app.add_controller(UntypedCountedController);
+
+    app.new_route("/counted".to_string());
+    assert_eq!(PARSES.with(Cell::get), 1);
+
+    app.new_route("/elsewhere".to_string());
+    assert_eq!(PARSES.with(Cell::get), 2);
+}
+
+#[derive(AppPath, Debug, PartialEq)]
+enum FileRoute {
+    #[path("/files/:user_id/*file_path")]
+    UserFile { user_id: u32, file_path: String },
+
+    #[path("/files/:user_id/:folder?")]
+    Folder {
+        user_id: u32,
+        folder: Option<String>,
+    },
+}
+
+/// The template, captures, query and fragment of a `RouteParams`
+type RouteParts = (
+    Option<String>,
+    BTreeMap<String, String>,
+    Vec<(String, String)>,
+    Option<String>,
+);
+
+/// Keeps the `RouteParams` of the last route, minus the parts it can't clone
+#[derive(Default)]
+struct RouteParamsController {
+    last: Rc<RefCell<Option<RouteParts>>>,
+}
+
+impl Hash for RouteParamsController {
+    fn hash<H: Hasher>(&self, _state: &mut H) {}
+}
+
+impl Controller for RouteParamsController {
+    type Effect = String;
+
+    fn params(&mut self, params: &RouteParams) -> Option<()> {
+        *self.last.borrow_mut() = Some((
+            params.template.clone(),
+            params.captures.clone(),
+            params.query.clone(),
+            params.fragment.clone(),
+        ));
+
+        Some(())
+    }
+
+    fn start(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+fn captures(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+    pairs
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+#[test]
+fn route_params_are_filled_in() {
+    let controller = RouteParamsController::default();
+    let last = controller.last.clone();
+
+    let mut app = App::new();
+    app.add_path::<FileRoute>();
+    app.add_path_controller(UserDetailController {
+        user_id: 0,
+        log: Log::default(),
+    });
+    app.add_controller(controller);
+
+    app.new_route("/users/12?tab=friends&q=a+b%21&empty#top".to_string());
+    assert_eq!(
+        *last.borrow(),
+        Some((
+            Some("/users/:user_id".to_string()),
+            captures(&[("user_id", "12")]),
+            vec![
+                ("tab".to_string(), "friends".to_string()),
+                ("q".to_string(), "a b!".to_string()),
+                ("empty".to_string(), "".to_string()),
+            ],
+            Some("top".to_string()),
+        ))
+    );
+
+    app.new_route("/files/3/taxes/2019%20return.pdf".to_string());
+    assert_eq!(
+        *last.borrow(),
+        Some((
+            Some("/files/:user_id/*file_path".to_string()),
+            captures(&[("user_id", "3"), ("file_path", "taxes/2019 return.pdf")]),
+            vec![],
+            None,
+        ))
+    );
+
+    app.new_route("/files/3".to_string());
+    assert_eq!(
+        *last.borrow(),
+        Some((
+            Some("/files/:user_id/:folder?".to_string()),
+            captures(&[("user_id", "3")]),
+            vec![],
+            None,
+        ))
+    );
+
+    app.new_route("/elsewhere".to_string());
+    assert_eq!(*last.borrow(), Some((None, BTreeMap::new(), vec![], None)));
+}
+
+#[test]
+fn typed_path_ignores_fragment() {
+    let log = Log::default();
+    let mut app = App::new();
+    app.add_path_controller(UserDetailController {
+        user_id: 0,
+        log: log.clone(),
+    });
+
+    app.new_route("/users/7#details".to_string());
+    assert_eq!(*log.borrow(), vec!["start 7"]);
+}
+
+/// Runs on the routes starting with `prefix`, and restarts whenever the route
+/// changes
+struct ScreenController {
+    name: &'static str,
+    prefix: &'static str,
+    route: String,
+    log: Log,
+}
+
+impl ScreenController {
+    fn new(name: &'static str, prefix: &'static str, log: &Log) -> ScreenController {
+        ScreenController {
+            name,
+            prefix,
+            route: String::new(),
+            log: log.clone(),
+        }
+    }
+}
+
+impl Hash for ScreenController {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.route.hash(state);
+    }
+}
+
+impl Controller for ScreenController {
+    type Effect = String;
+
+    fn params(&mut self, params: &RouteParams) -> Option<()> {
+        self.log.borrow_mut().push(format!("params {}", self.name));
+        self.route = params.data.name.clone();
+
+        if self.route.starts_with(self.prefix) {
+            Some(())
+        } else {
+            None
+        }
+    }
+
+    fn start(&self) -> Vec<String> {
+        vec![format!("start {}", self.name)]
+    }
+
+    fn stop(&self) -> Vec<String> {
+        vec![format!("stop {}", self.name)]
+    }
+}
+
+#[test]
+fn stops_run_before_starts() {
+    let log = Log::default();
+    let mut app = App::new();
+    app.add_controller(ScreenController::new("layout", "/", &log));
+    app.add_controller(ScreenController::new("users", "/users", &log));
+    app.add_controller(ScreenController::new("settings", "/settings", &log));
+
+    let handler_log = log.clone();
+    app.set_effect_handler(move |effect: &String| handler_log.borrow_mut().push(effect.clone()));
+
+    app.new_route("/users".to_string());
+    log.borrow_mut().clear();
+
+    let transition = app.new_route("/settings".to_string());
+    assert_eq!(
+        *log.borrow(),
+        vec![
+            "params layout",
+            "params users",
+            "params settings",
+            "stop users",
+            "stop layout",
+            "start layout",
+            "start settings",
+        ]
+    );
+    assert_eq!(
+        transition
+            .calls
+            .iter()
+            .map(|call| (call.controller, call.call))
+            .collect::<Vec<_>>(),
+        vec![
+            (1, Lifecycle::Stop),
+            (0, Lifecycle::Stop),
+            (0, Lifecycle::Start),
+            (2, Lifecycle::Start),
+        ]
+    );
+}
@@ -1,4 +1,4 @@
-use rs_frame::{AppPath, PathParseErr};
+use rs_frame::{AppPath, PathParseErr, QueryWriteErr};
 use serde::{Deserialize, Serialize};
 
 #[derive(AppPath, Debug, PartialEq)]
@@ -213,19 +213,39 @@ fn no_params_simple_query_url_decoding() {
     );
 }
 
-// TODO - uncomment after https://github.com/samscott89/serde_qs/pull/18
-// #[test]
-// fn no_params_simple_query_url_decoding_plus_sign() {
-//     let path = UsersListWithQuery::from_str("/users?keyword=%2b").unwrap();
-//     assert_eq!(path, UsersListWithQuery { query: {
-//         UserListQuery {
-//             limit: None,
-//             offset: None,
-//             keyword: Some("+".to_string()),
-//             friends_only: false,
-//         }
-//     } });
-// }
+#[test]
+fn no_params_simple_query_url_decoding_plus_sign() {
+    let path: UsersListWithQuery = "/users?keyword=%2b".parse().unwrap();
+    assert_eq!(
+        path,
+        UsersListWithQuery {
+            query: UserListQuery {
+                limit: None,
+                offset: None,
+                keyword: Some("+".to_string()),
+                friends_only: false,
+            }
+        }
+    );
+    assert_eq!(path.to_string(), "/users?keyword=%2B&friends_only=false");
+}
+
+#[test]
+fn no_params_simple_query_url_decoding_plus_as_space() {
+    let path: UsersListWithQuery = "/users?keyword=a+b".parse().unwrap();
+    assert_eq!(
+        path,
+        UsersListWithQuery {
+            query: UserListQuery {
+                limit: None,
+                offset: None,
+                keyword: Some("a b".to_string()),
+                friends_only: false,
+            }
+        }
+    );
+    assert_eq!(path.to_string(), "/users?keyword=a+b&friends_only=false");
+}
 
 #[derive(AppPath, Debug, PartialEq)]
 #[path("/users/:user_id")]
@@ -592,3 +612,1728 @@ fn test_only_question_mark() {
         }
     );
 }
+
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/files/:user_id/*file_path")]
+struct UserFilePath {
+    user_id: u32,
+    file_path: String,
+}
+
+#[test]
+fn splat_string() {
+    let path: UserFilePath = "/files/12/documents/2019/taxes.pdf".parse().unwrap();
+    assert_eq!(
+        path,
+        UserFilePath {
+            user_id: 12,
+            file_path: "documents/2019/taxes.pdf".to_string(),
+        }
+    );
+    assert_eq!(path.to_string(), "/files/12/documents/2019/taxes.pdf");
+}
+
+#[test]
+fn splat_empty() {
+    let path: UserFilePath = "/files/12/".parse().unwrap();
+    assert_eq!(
+        path,
+        UserFilePath {
+            user_id: 12,
+            file_path: "".to_string(),
+        }
+    );
+    assert_eq!(path.to_string(), "/files/12/");
+
+    let path: Result<UserFilePath, _> = "/files/12".parse();
+    match path {
+        Err(PathParseErr::NoMatches) => {}
+        _ => assert!(false),
+    }
+}
+
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/docs/*sections")]
+struct DocsPath {
+    sections: Vec<String>,
+}
+
+#[test]
+fn splat_vec() {
+    let path: DocsPath = "/docs/guide/routing/splats".parse().unwrap();
+    assert_eq!(
+        path,
+        DocsPath {
+            sections: vec![
+                "guide".to_string(),
+                "routing".to_string(),
+                "splats".to_string()
+            ],
+        }
+    );
+    assert_eq!(path.to_string(), "/docs/guide/routing/splats");
+}
+
+#[test]
+fn splat_vec_empty_round_trips() {
+    let path = DocsPath { sections: vec![] };
+    assert_eq!(path.to_string(), "/docs/");
+    assert_eq!(path.to_string().parse::<DocsPath>().unwrap(), path);
+
+    let path = DocsPath {
+        sections: vec!["guide".to_string(), "".to_string()],
+    };
+    assert_eq!(path.to_string(), "/docs/guide/");
+    assert_eq!(path.to_string().parse::<DocsPath>().unwrap(), path);
+}
+
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/users/:user_id/posts/:page?")]
+struct UserPostsPath {
+    user_id: u32,
+    page: Option<u32>,
+}
+
+#[test]
+fn optional_param_present() {
+    let path: UserPostsPath = "/users/5/posts/3".parse().unwrap();
+    assert_eq!(
+        path,
+        UserPostsPath {
+            user_id: 5,
+            page: Some(3),
+        }
+    );
+    assert_eq!(path.to_string(), "/users/5/posts/3");
+}
+
+#[test]
+fn optional_param_missing() {
+    let path: UserPostsPath = "/users/5/posts".parse().unwrap();
+    assert_eq!(
+        path,
+        UserPostsPath {
+            user_id: 5,
+            page: None,
+        }
+    );
+    assert_eq!(path.to_string(), "/users/5/posts");
+}
+
+#[test]
+fn optional_param_invalid_type() {
+    let path: Result<UserPostsPath, _> = "/users/5/posts/last".parse();
+    match path {
+        Err(PathParseErr::ParamParseErr(_)) => {}
+        _ => assert!(false),
+    }
+
+    let path: Result<UserPostsPath, _> = "/users/5/posts/".parse();
+    match path {
+        Err(PathParseErr::ParamParseErr(_)) => {}
+        _ => assert!(false),
+    }
+}
+
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/tags/:tag?")]
+struct TagPath {
+    tag: Option<String>,
+}
+
+#[test]
+fn optional_param_empty_round_trips() {
+    let path = TagPath {
+        tag: Some("".to_string()),
+    };
+    assert_eq!(path.to_string(), "/tags/");
+    assert_eq!(path.to_string().parse::<TagPath>().unwrap(), path);
+
+    let path = TagPath { tag: None };
+    assert_eq!(path.to_string(), "/tags");
+    assert_eq!(path.to_string().parse::<TagPath>().unwrap(), path);
+}
+
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/articles/:article_id(\\d+)/:slug([a-z-]+)")]
+struct ArticlePath {
+    article_id: u64,
+    slug: String,
+}
+
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/articles/:slug([a-z-]+)")]
+struct ArticleBySlugPath {
+    slug: String,
+}
+
+#[test]
+fn constrained_params() {
+    let path: ArticlePath = "/articles/42/hello-world".parse().unwrap();
+    assert_eq!(
+        path,
+        ArticlePath {
+            article_id: 42,
+            slug: "hello-world".to_string(),
+        }
+    );
+    assert_eq!(path.to_string(), "/articles/42/hello-world");
+}
+
+#[test]
+fn constrained_params_no_match() {
+    let path: Result<ArticlePath, _> = "/articles/abc/hello-world".parse();
+    match path {
+        Err(PathParseErr::NoMatches) => {}
+        _ => assert!(false),
+    }
+
+    let path: Result<ArticlePath, _> = "/articles/42/Hello_World".parse();
+    match path {
+        Err(PathParseErr::NoMatches) => {}
+        _ => assert!(false),
+    }
+}
+
+#[test]
+fn constrained_params_overlapping_routes() {
+    let path: Result<ArticleBySlugPath, _> = "/articles/42".parse();
+    match path {
+        Err(PathParseErr::NoMatches) => {}
+        _ => assert!(false),
+    }
+
+    let path: ArticleBySlugPath = "/articles/release-notes".parse().unwrap();
+    assert_eq!(
+        path,
+        ArticleBySlugPath {
+            slug: "release-notes".to_string(),
+        }
+    );
+}
+
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/posts/:id-:slug")]
+struct PostSlugPath {
+    id: u32,
+    slug: String,
+}
+
+#[test]
+fn multiple_params_per_segment() {
+    let path: PostSlugPath = "/posts/12-my-first-post".parse().unwrap();
+    assert_eq!(
+        path,
+        PostSlugPath {
+            id: 12,
+            slug: "my-first-post".to_string(),
+        }
+    );
+    assert_eq!(path.to_string(), "/posts/12-my-first-post");
+
+    let path: Result<PostSlugPath, _> = "/posts/12".parse();
+    match path {
+        Err(PathParseErr::NoMatches) => {}
+        _ => assert!(false),
+    }
+}
+
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/v:version/files/:name~:revision")]
+struct VersionedFilePath {
+    version: u8,
+    name: String,
+    revision: u32,
+}
+
+#[test]
+fn literal_affixes_within_segment() {
+    let path: VersionedFilePath = "/v2/files/report~3".parse().unwrap();
+    assert_eq!(
+        path,
+        VersionedFilePath {
+            version: 2,
+            name: "report".to_string(),
+            revision: 3,
+        }
+    );
+    assert_eq!(path.to_string(), "/v2/files/report~3");
+
+    let path: Result<VersionedFilePath, _> = "/2/files/report~3".parse();
+    match path {
+        Err(PathParseErr::NoMatches) => {}
+        _ => assert!(false),
+    }
+}
+
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/v1.0/items")]
+struct VersionedItemsPath {}
+
+#[test]
+fn static_dot_is_literal() {
+    let path: VersionedItemsPath = "/v1.0/items".parse().unwrap();
+    assert_eq!(path, VersionedItemsPath {});
+    assert_eq!(path.to_string(), "/v1.0/items");
+
+    let path: Result<VersionedItemsPath, _> = "/v1x0/items".parse();
+    match path {
+        Err(PathParseErr::NoMatches) => {}
+        _ => assert!(false),
+    }
+}
+
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/files/:name.:ext")]
+struct FileExtensionPath {
+    name: String,
+    ext: String,
+}
+
+#[test]
+fn file_extension_params() {
+    let path: FileExtensionPath = "/files/report.pdf".parse().unwrap();
+    assert_eq!(
+        path,
+        FileExtensionPath {
+            name: "report".to_string(),
+            ext: "pdf".to_string(),
+        }
+    );
+    assert_eq!(path.to_string(), "/files/report.pdf");
+
+    let path: Result<FileExtensionPath, _> = "/files/report".parse();
+    match path {
+        Err(PathParseErr::NoMatches) => {}
+        _ => assert!(false),
+    }
+}
+
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/tarballs/:name.tar.gz/:size")]
+struct TarballPath {
+    name: String,
+    size: u32,
+}
+
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/files/:name.tar.gz")]
+struct TarballFilePath {
+    name: String,
+}
+
+#[test]
+fn param_takes_earlier_suffix() {
+    let path: TarballFilePath = "/files/a.tar.gz.tar.gz".parse().unwrap();
+    assert_eq!(path.name, "a.tar.gz");
+
+    let path: TarballPath = "/tarballs/a.tar.gz.tar.gz/3".parse().unwrap();
+    assert_eq!(path.name, "a.tar.gz");
+}
+
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/a.b+c(d)e|f[g]h{i}j^k$l\\m/:id")]
+struct MetaCharsPath {
+    id: u32,
+}
+
+#[test]
+fn static_meta_chars_are_literal() {
+    let url = "/a.b+c(d)e|f[g]h{i}j^k$l\\m/7";
+
+    let path: MetaCharsPath = url.parse().unwrap();
+    assert_eq!(path, MetaCharsPath { id: 7 });
+    assert_eq!(path.to_string(), url);
+    assert_eq!(
+        MetaCharsPath::path_pattern(),
+        r"^/a\.b\+c\(d\)e\|f\[g\]h\{i\}j\^k\$l\\m/(?P<id>[^/]+)$"
+    );
+
+    for url in &[
+        "/aXb+c(d)e|f[g]h{i}j^k$l\\m/7",
+        "/a.bbc(d)e|f[g]h{i}j^k$l\\m/7",
+        "/a.b+cde|f[g]h{i}j^k$l\\m/7",
+        "/a.b+c(d)e/7",
+        "/a.b+c(d)e|fgh{i}j^k$l\\m/7",
+        "/a.b+c(d)e|f[g]hiiiij^k$l\\m/7",
+    ] {
+        let path: Result<MetaCharsPath, _> = url.parse();
+        match path {
+            Err(PathParseErr::NoMatches) => {}
+            _ => assert!(false),
+        }
+    }
+}
+
+#[test]
+fn percent_decoded_params() {
+    let path: UserFriendDetailPath = "/users/612451/friends/John%20Doe".parse().unwrap();
+    assert_eq!(
+        path,
+        UserFriendDetailPath {
+            user_id: 612451,
+            friend_name: "John Doe".to_string()
+        }
+    );
+
+    let path: UserFriendDetailPath = "/users/612451/friends/%E7%94%B0%E4%B8%AD".parse().unwrap();
+    assert_eq!(path.friend_name, "田中");
+
+    let path: Result<UserFriendDetailPath, _> = "/users/612451/friends/%FF".parse();
+    match path {
+        Err(PathParseErr::ParamParseErr(_)) => {}
+        _ => assert!(false),
+    }
+}
+
+#[test]
+fn percent_encoded_params_round_trip() {
+    for friend_name in &[
+        "John Doe",
+        "a/b",
+        "what?",
+        "#hashtag",
+        "100%",
+        "{braces}",
+        "<tag>",
+        "back`tick",
+        "\"quoted\"",
+        "田中",
+        "🌮🌮🌮",
+        "semi;colon&and=equals+plus",
+    ] {
+        let path = UserFriendDetailPath {
+            user_id: 1,
+            friend_name: friend_name.to_string(),
+        };
+
+        let url = path.to_string();
+        let parsed: UserFriendDetailPath = url.parse().unwrap();
+        assert_eq!(parsed, path);
+    }
+
+    let path = UserFriendDetailPath {
+        user_id: 1,
+        friend_name: "John Doe/?#%".to_string(),
+    };
+    assert_eq!(path.to_string(), "/users/1/friends/John%20Doe%2F%3F%23%25");
+}
+
+#[test]
+fn percent_encoded_splat_round_trip() {
+    let path = UserFilePath {
+        user_id: 1,
+        file_path: "my documents/100% done?.txt".to_string(),
+    };
+    assert_eq!(
+        path.to_string(),
+        "/files/1/my%20documents/100%25%20done%3F.txt"
+    );
+    assert_eq!(path.to_string().parse::<UserFilePath>().unwrap(), path);
+
+    let path = DocsPath {
+        sections: vec!["a/b".to_string(), "c d".to_string()],
+    };
+    assert_eq!(path.to_string(), "/docs/a%2Fb/c%20d");
+    assert_eq!(path.to_string().parse::<DocsPath>().unwrap(), path);
+
+    let path = UserPostsPath {
+        user_id: 1,
+        page: Some(2),
+    };
+    assert_eq!(path.to_string().parse::<UserPostsPath>().unwrap(), path);
+}
+
+fn assert_matches_like_regex<T>(urls: &[&str])
+where
+    T: AppPath + std::str::FromStr<Err = PathParseErr>,
+{
+    let regex = rs_frame::Regex::new(&T::path_pattern()).unwrap();
+
+    for url in urls {
+        let regex_match = regex.is_match(url);
+        let matcher_match = match url.parse::<T>() {
+            Err(PathParseErr::NoMatches) => false,
+            _ => true,
+        };
+
+        assert_eq!(regex_match, matcher_match, "url: {}", url);
+    }
+}
+
+#[test]
+fn generated_matcher_agrees_with_regex() {
+    let urls = [
+        "",
+        "/",
+        "/users",
+        "/users/",
+        "/users/1",
+        "/users/1/",
+        "/users/1/friends",
+        "/users/1/friends/",
+        "/users/1/friends/steve",
+        "/users/1/friends/steve/",
+        "/users/1/friends/steve/x",
+        "/users/田中/friends/🌮",
+        "/users//friends/steve",
+        "users/1",
+        "/posts/12-my-post",
+        "/posts/-12-post",
+        "/posts/12-",
+        "/posts/-",
+        "/posts/12",
+        "/posts/12/my-post",
+        "/users/5/posts",
+        "/users/5/posts/",
+        "/users/5/posts/3",
+        "/users/5/posts/3/4",
+        "/users/5/postsx",
+        "/files/12/a",
+        "/files/12/a/b/c",
+        "/files/12/",
+        "/files/12",
+        "/docs/a/b",
+        "/docs/",
+        "/v2/files/report~3",
+        "/v/files/report~3",
+        "/v2/files/report~",
+        "/v2/files/~3",
+        "/v2/files/a~b~3",
+        "/files/report.pdf",
+        "/files/archive.tar.gz",
+        "/files/.hidden",
+        "/files/report.",
+        "/files/a.tar.gz",
+        "/files/a.tar.gz.tar.gz",
+        "/files/.tar.gz",
+        "/files/a.tar.gz.zip",
+        "/tarballs/a.tar.gz/3",
+        "/tarballs/a.tar.gz.tar.gz/3",
+        "/tarballs/.tar.gz/3",
+        "/tarballs/a.tar.gzip/3",
+    ];
+
+    assert_matches_like_regex::<UsersListPath>(&urls);
+    assert_matches_like_regex::<UserDetailPath>(&urls);
+    assert_matches_like_regex::<UserFriendDetailPath>(&urls);
+    assert_matches_like_regex::<PostSlugPath>(&urls);
+    assert_matches_like_regex::<UserPostsPath>(&urls);
+    assert_matches_like_regex::<UserFilePath>(&urls);
+    assert_matches_like_regex::<DocsPath>(&urls);
+    assert_matches_like_regex::<VersionedFilePath>(&urls);
+    assert_matches_like_regex::<FileExtensionPath>(&urls);
+    assert_matches_like_regex::<ArticlePath>(&urls);
+    assert_matches_like_regex::<TarballPath>(&urls);
+    assert_matches_like_regex::<TarballFilePath>(&urls);
+}
+
+#[test]
+fn display_query() {
+    let path = UserDetailExtraPath {
+        user_id: 8,
+        query: Some(UserListQuery {
+            limit: Some(55),
+            offset: None,
+            keyword: Some("a b&c".to_string()),
+            friends_only: true,
+        }),
+    };
+    assert_eq!(
+        path.to_string(),
+        "/users/8?limit=55&keyword=a+b%26c&friends_only=true"
+    );
+    assert_eq!(
+        path.to_string().parse::<UserDetailExtraPath>().unwrap(),
+        path
+    );
+
+    let path = UserDetailExtraPath {
+        user_id: 8,
+        query: None,
+    };
+    assert_eq!(path.to_string(), "/users/8");
+    assert_eq!(path.query_string(), None);
+}
+
+#[test]
+fn display_multiple_queries() {
+    let mut path = ExpiredSubmissionsPath {
+        project_id: "43".to_string(),
+        exam_id: 10,
+        query: Some(SubmissionsQuery {
+            column: Some("name".to_string()),
+            direction: Some(SortDirection::Asc),
+            keyword: None,
+        }),
+        limit: Some(LimitOffsetQuery {
+            limit: Some(20),
+            offset: None,
+        }),
+    };
+    assert_eq!(
+        path.to_string(),
+        "/p/43/exams/10/submissions_expired?column=name&direction=asc&limit=20"
+    );
+
+    path.query = Some(SubmissionsQuery {
+        column: None,
+        direction: None,
+        keyword: None,
+    });
+    assert_eq!(
+        path.to_string(),
+        "/p/43/exams/10/submissions_expired?limit=20"
+    );
+
+    path.limit = None;
+    assert_eq!(path.to_string(), "/p/43/exams/10/submissions_expired");
+}
+
+#[test]
+fn write_query() {
+    let path = UserDetailVecQueryPath {
+        user_id: 1,
+        query: Some(VecQuery {
+            friend_ids: vec![1, 20],
+        }),
+    };
+
+    let mut query = String::new();
+    path.write_query(&mut query).unwrap();
+    assert_eq!(query, "friend_ids[0]=1&friend_ids[1]=20");
+    assert_eq!(path.query_string(), Some(query));
+
+    let mut query = String::new();
+    UsersListPath {}.write_query(&mut query).unwrap();
+    assert_eq!(query, "");
+}
+
+#[derive(AppPath, Debug, PartialEq)]
+enum Route {
+    #[path("/users")]
+    Users,
+
+    #[path("/users/:user_id")]
+    UserDetail {
+        user_id: u32,
+
+        #[query]
+        query: Option<UserListQuery>,
+    },
+
+    #[path("/users/new")]
+    NewUser {},
+
+    Files(UserFilePath),
+}
+
+#[test]
+fn enum_unit_variant() {
+    assert_eq!("/users".parse::<Route>().unwrap(), Route::Users);
+    assert_eq!(Route::Users.to_string(), "/users");
+}
+
+#[test]
+fn enum_named_fields() {
+    let route: Route = "/users/8?limit=5&friends_only=true".parse().unwrap();
+
+    assert_eq!(
+        route,
+        Route::UserDetail {
+            user_id: 8,
+            query: Some(UserListQuery {
+                limit: Some(5),
+                offset: None,
+                keyword: None,
+                friends_only: true,
+            }),
+        }
+    );
+    assert_eq!(route.to_string(), "/users/8?limit=5&friends_only=true");
+    assert_eq!(
+        route.query_string(),
+        Some("limit=5&friends_only=true".to_string())
+    );
+}
+
+#[test]
+fn enum_tries_variants_in_order() {
+    // "new" isn't a u32, so UserDetail fails and NewUser gets its turn
+    assert_eq!("/users/new".parse::<Route>().unwrap(), Route::NewUser {});
+    assert_eq!(Route::NewUser {}.to_string(), "/users/new");
+}
+
+#[test]
+fn enum_wrapped_path() {
+    let route: Route = "/files/3/photos/cat.jpg".parse().unwrap();
+
+    assert_eq!(
+        route,
+        Route::Files(UserFilePath {
+            user_id: 3,
+            file_path: "photos/cat.jpg".to_string(),
+        })
+    );
+    assert_eq!(route.to_string(), "/files/3/photos/cat.jpg");
+}
+
+#[test]
+fn enum_no_matches() {
+    assert!(matches!(
+        "/nowhere".parse::<Route>(),
+        Err(PathParseErr::NoMatches)
+    ));
+}
+
+#[test]
+fn enum_reports_error_from_matching_variant() {
+    assert!(matches!(
+        "/users/99999999999".parse::<Route>(),
+        Err(PathParseErr::ParamParseErr(_))
+    ));
+}
+
+#[test]
+fn enum_path_pattern() {
+    assert_eq!(
+        Route::path_pattern(),
+        [
+            UsersListPath::path_pattern(),
+            UserDetailPath::path_pattern(),
+            "^/users/new$".to_string(),
+            UserFilePath::path_pattern(),
+        ]
+        .iter()
+        .map(|pattern| pattern
+            .replace("(?P<user_id>", "(?:")
+            .replace("(?P<file_path>", "(?:"))
+        .collect::<Vec<_>>()
+        .join("|")
+    );
+
+    // Both `UserDetail` and `UserFile` have a `user_id`
+    let regex = rs_frame::Regex::new(&Route::path_pattern()).unwrap();
+    assert!(regex.is_match("/users/1"));
+    assert!(regex.is_match("/files/1/a.txt"));
+    assert!(!regex.is_match("/elsewhere"));
+}
+
+#[test]
+fn enum_path_patterns() {
+    assert_eq!(
+        Route::path_patterns(),
+        vec![
+            UsersListPath::path_pattern(),
+            UserDetailPath::path_pattern(),
+            "^/users/new$".to_string(),
+            UserFilePath::path_pattern(),
+        ]
+    );
+    assert_eq!(Route::path_patterns().len(), Route::path_templates().len());
+}
+
+#[test]
+fn param_parse_err_details() {
+    let err = "/users/not_a_u64".parse::<UserDetailPath>().unwrap_err();
+
+    match err {
+        PathParseErr::ParamParseErr(ref e) => {
+            assert_eq!(e.field, "user_id");
+            assert_eq!(e.segment, "not_a_u64");
+            assert_eq!(e.pattern, "/users/:user_id");
+            assert_eq!(e.source.to_string(), "invalid digit found in string");
+        }
+        _ => panic!("expected a ParamParseErr, got {:?}", err),
+    }
+
+    assert_eq!(
+        err.to_string(),
+        "couldn't parse `not_a_u64` into `user_id` for `/users/:user_id`: invalid digit found in string"
+    );
+    assert_eq!(
+        std::error::Error::source(&err).unwrap().to_string(),
+        "invalid digit found in string"
+    );
+}
+
+#[derive(Debug, PartialEq)]
+struct Color(String);
+
+impl std::str::FromStr for Color {
+    // Only `Display`, not `std::error::Error`
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Color, String> {
+        match s {
+            "red" | "green" | "blue" => Ok(Color(s.to_string())),
+            _ => Err(format!("`{}` isn't a color", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for Color {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/colors/:color")]
+struct ColorPath {
+    color: Color,
+
+    #[query_param]
+    mix: Option<Color>,
+}
+
+#[test]
+fn param_err_without_error_impl() {
+    assert_eq!(
+        "/colors/red?mix=blue".parse::<ColorPath>().unwrap(),
+        ColorPath {
+            color: Color("red".to_string()),
+            mix: Some(Color("blue".to_string())),
+        }
+    );
+
+    match "/colors/teal".parse::<ColorPath>() {
+        Err(PathParseErr::ParamParseErr(e)) => {
+            assert_eq!(e.source.to_string(), "`teal` isn't a color")
+        }
+        other => panic!("expected a ParamParseErr, got {:?}", other),
+    }
+
+    match "/colors/red?mix=teal".parse::<ColorPath>() {
+        Err(PathParseErr::QueryParseErr(e)) => {
+            assert_eq!(e.source.to_string(), "`teal` isn't a color")
+        }
+        other => panic!("expected a QueryParseErr, got {:?}", other),
+    }
+}
+
+#[test]
+fn param_parse_err_undecodable() {
+    let err = "/users/8/friends/%FF"
+        .parse::<UserFriendDetailPath>()
+        .unwrap_err();
+
+    match err {
+        PathParseErr::ParamParseErr(ref e) => {
+            assert_eq!(e.field, "friend_name");
+            assert_eq!(e.segment, "%FF");
+            assert!(e.source.is::<std::str::Utf8Error>());
+        }
+        _ => panic!("expected a ParamParseErr, got {:?}", err),
+    }
+}
+
+#[test]
+fn query_parse_err_details() {
+    let err = "/users?offset=test"
+        .parse::<UsersListWithQuery>()
+        .unwrap_err();
+
+    match err {
+        PathParseErr::QueryParseErr(ref e) => {
+            assert_eq!(e.field, "query");
+            assert_eq!(e.query, "offset=test");
+            assert_eq!(e.pattern, "/users");
+        }
+        _ => panic!("expected a QueryParseErr, got {:?}", err),
+    }
+
+    assert!(err
+        .to_string()
+        .starts_with("couldn't parse the query string `offset=test` into `query` for `/users`: "));
+    assert!(std::error::Error::source(&err).is_some());
+}
+
+#[test]
+fn path_parse_err_is_boxable() {
+    fn user_id(app_path: &str) -> Result<u64, Box<dyn std::error::Error + Send + Sync>> {
+        let path: UserDetailPath = app_path.parse()?;
+        Ok(path.user_id)
+    }
+
+    assert_eq!(user_id("/users/5").unwrap(), 5);
+    assert_eq!(
+        user_id("/posts/5").unwrap_err().to_string(),
+        "the path didn't match"
+    );
+    assert_eq!(
+        "/users"
+            .parse::<UsersListWithQuery>()
+            .unwrap_err()
+            .to_string(),
+        "the query string is missing"
+    );
+}
+
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/users/:user_id")]
+struct UserDetailStrictQueryPath {
+    user_id: u32,
+
+    #[query(strict)]
+    query: Option<UserListQuery>,
+}
+
+#[test]
+fn strict_query_missing() {
+    let path: UserDetailStrictQueryPath = "/users/8".parse().unwrap();
+    assert_eq!(path.query, None);
+
+    let path: UserDetailStrictQueryPath = "/users/8?".parse().unwrap();
+    assert_eq!(path.query, None);
+}
+
+#[test]
+fn strict_query_present() {
+    let path: UserDetailStrictQueryPath = "/users/8?limit=5".parse().unwrap();
+    assert_eq!(
+        path.query,
+        Some(UserListQuery {
+            limit: Some(5),
+            offset: None,
+            keyword: None,
+            friends_only: false,
+        })
+    );
+}
+
+#[test]
+fn strict_query_malformed() {
+    match "/users/8?limit=abc".parse::<UserDetailStrictQueryPath>() {
+        Err(PathParseErr::QueryParseErr(e)) => {
+            assert_eq!(e.field, "query");
+            assert_eq!(e.query, "limit=abc");
+        }
+        other => panic!("expected a QueryParseErr, got {:?}", other),
+    }
+}
+
+#[test]
+fn lenient_query_malformed() {
+    let path: UserDetailExtraPath = "/users/8?limit=abc".parse().unwrap();
+    assert_eq!(path.query, None);
+}
+
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/p/:project_id/exams/:exam_id/submissions_expired")]
+#[query(strict)]
+struct StrictSubmissionsPath {
+    project_id: String,
+    exam_id: u64,
+
+    #[query]
+    query: Option<SubmissionsQuery>,
+
+    #[query(lenient)]
+    limit: Option<LimitOffsetQuery>,
+}
+
+#[test]
+fn strict_by_default() {
+    match "/p/43/exams/10/submissions_expired?direction=sideways".parse::<StrictSubmissionsPath>() {
+        Err(PathParseErr::QueryParseErr(e)) => assert_eq!(e.field, "query"),
+        other => panic!("expected a QueryParseErr, got {:?}", other),
+    }
+
+    let path: StrictSubmissionsPath = "/p/43/exams/10/submissions_expired?limit=many"
+        .parse()
+        .unwrap();
+    assert_eq!(path.query, None);
+    assert_eq!(path.limit, None);
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+struct ItemFilter {
+    kind: String,
+}
+
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/items")]
+struct ItemsPath {
+    #[query_param]
+    page: Option<u32>,
+
+    #[query(strict)]
+    filter: Option<ItemFilter>,
+}
+
+#[test]
+fn strict_query_needs_its_own_keys() {
+    let path: ItemsPath = "/items?page=2".parse().unwrap();
+    assert_eq!(
+        path,
+        ItemsPath {
+            page: Some(2),
+            filter: None,
+        }
+    );
+
+    let path: ItemsPath = "/items?page=2&kind=book".parse().unwrap();
+    assert_eq!(
+        path.filter,
+        Some(ItemFilter {
+            kind: "book".to_string()
+        })
+    );
+
+    match "/items?page=2&kind[a]=book".parse::<ItemsPath>() {
+        Err(PathParseErr::QueryParseErr(e)) => assert_eq!(e.field, "filter"),
+        other => panic!("expected a QueryParseErr, got {:?}", other),
+    }
+}
+
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/search")]
+struct SearchPath {
+    #[query_param(rename = "q")]
+    keyword: String,
+
+    #[query_param]
+    page: Option<u32>,
+}
+
+#[test]
+fn query_params() {
+    let path: SearchPath = "/search?q=rust%20lang&page=2".parse().unwrap();
+    assert_eq!(
+        path,
+        SearchPath {
+            keyword: "rust lang".to_string(),
+            page: Some(2),
+        }
+    );
+    assert_eq!(path.to_string(), "/search?q=rust+lang&page=2");
+    assert_eq!(path, "/search?q=rust+lang&page=2".parse().unwrap());
+}
+
+#[test]
+fn query_params_optional_missing() {
+    let path: SearchPath = "/search?q=cats".parse().unwrap();
+    assert_eq!(path.page, None);
+    assert_eq!(path.to_string(), "/search?q=cats");
+}
+
+#[test]
+fn query_params_round_trip_reserved_chars() {
+    let path = SearchPath {
+        keyword: "a&b=c+d%".to_string(),
+        page: None,
+    };
+
+    assert_eq!(path.to_string(), "/search?q=a%26b%3Dc%2Bd%25");
+    assert_eq!(path.to_string().parse::<SearchPath>().unwrap(), path);
+}
+
+#[test]
+fn query_params_required_missing() {
+    match "/search".parse::<SearchPath>() {
+        Err(PathParseErr::NoQueryString) => {}
+        other => panic!("expected NoQueryString, got {:?}", other),
+    }
+
+    match "/search?page=2".parse::<SearchPath>() {
+        Err(PathParseErr::QueryParseErr(e)) => {
+            assert_eq!(e.field, "keyword");
+            assert_eq!(e.key, Some("q"));
+            assert_eq!(e.source.to_string(), "`q` is missing");
+        }
+        other => panic!("expected a QueryParseErr, got {:?}", other),
+    }
+}
+
+#[test]
+fn query_params_invalid() {
+    let err = "/search?q=cats&page=two".parse::<SearchPath>().unwrap_err();
+
+    match err {
+        PathParseErr::QueryParseErr(ref e) => {
+            assert_eq!(e.field, "page");
+            assert_eq!(e.key, Some("page"));
+            assert_eq!(e.query, "q=cats&page=two");
+        }
+        _ => panic!("expected a QueryParseErr, got {:?}", err),
+    }
+
+    assert_eq!(
+        err.to_string(),
+        "couldn't parse `page` in the query string `q=cats&page=two` into `page` for `/search`: invalid digit found in string"
+    );
+}
+
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/users/:user_id")]
+struct UserDetailSortedPath {
+    user_id: u32,
+
+    #[query]
+    query: Option<LimitOffsetQuery>,
+
+    #[query_param]
+    sort: Option<String>,
+}
+
+#[test]
+fn query_params_with_query_struct() {
+    let path: UserDetailSortedPath = "/users/3?limit=10&sort=name".parse().unwrap();
+    assert_eq!(
+        path,
+        UserDetailSortedPath {
+            user_id: 3,
+            query: Some(LimitOffsetQuery {
+                limit: Some(10),
+                offset: None,
+            }),
+            sort: Some("name".to_string()),
+        }
+    );
+    assert_eq!(path.to_string(), "/users/3?limit=10&sort=name");
+}
+
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/p/:project_id/submissions")]
+struct OverlappingQueriesPath {
+    project_id: String,
+
+    #[query]
+    limit: Option<LimitOffsetQuery>,
+
+    #[query]
+    required_query: LimitOffsetQuery,
+
+    #[query_param]
+    offset: Option<u64>,
+}
+
+#[test]
+fn merged_queries_deduplicate_keys() {
+    let path: OverlappingQueriesPath = "/p/43/submissions?limit=10&offset=5".parse().unwrap();
+    let limit_offset = LimitOffsetQuery {
+        limit: Some(10),
+        offset: Some(5),
+    };
+
+    assert_eq!(
+        path,
+        OverlappingQueriesPath {
+            project_id: "43".to_string(),
+            limit: Some(LimitOffsetQuery {
+                limit: Some(10),
+                offset: Some(5),
+            }),
+            required_query: limit_offset,
+            offset: Some(5),
+        }
+    );
+    assert_eq!(path.to_string(), "/p/43/submissions?limit=10&offset=5");
+    assert_eq!(path.query_string(), Some("limit=10&offset=5".to_string()));
+}
+
+#[test]
+fn merged_queries_fill_in_keys() {
+    let path = OverlappingQueriesPath {
+        project_id: "43".to_string(),
+        limit: None,
+        required_query: LimitOffsetQuery {
+            limit: Some(10),
+            offset: None,
+        },
+        offset: Some(5),
+    };
+
+    assert_eq!(path.to_string(), "/p/43/submissions?limit=10&offset=5");
+}
+
+#[test]
+fn merged_queries_conflict() {
+    let path = OverlappingQueriesPath {
+        project_id: "43".to_string(),
+        limit: Some(LimitOffsetQuery {
+            limit: Some(10),
+            offset: None,
+        }),
+        required_query: LimitOffsetQuery {
+            limit: Some(20),
+            offset: None,
+        },
+        offset: None,
+    };
+
+    match path.write_query(&mut String::new()) {
+        Err(QueryWriteErr::Conflict { key, first, second }) => {
+            assert_eq!(key, "limit");
+            assert_eq!(first, "10");
+            assert_eq!(second, "20");
+        }
+        other => panic!("expected a Conflict, got {:?}", other),
+    }
+
+    match path.try_to_string() {
+        Err(QueryWriteErr::Conflict { key, .. }) => assert_eq!(key, "limit"),
+        other => panic!("expected a Conflict, got {:?}", other),
+    }
+
+    // `Display` can't fail, so the field declared first wins
+    assert_eq!(path.to_string(), "/p/43/submissions?limit=10");
+    assert_eq!(path.query_string(), Some("limit=10".to_string()));
+}
+
+#[test]
+fn try_to_string_without_conflict() {
+    let path: OverlappingQueriesPath = "/p/43/submissions?limit=10&offset=5".parse().unwrap();
+    assert_eq!(
+        path.try_to_string().unwrap(),
+        "/p/43/submissions?limit=10&offset=5"
+    );
+
+    let path = UserDetailPath { user_id: 3 };
+    assert_eq!(path.try_to_string().unwrap(), "/users/3");
+}
+
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/users/:user_id")]
+#[query(codec = "rs_frame::codec::RepeatedKeys")]
+struct UserDetailRepeatedKeysPath {
+    user_id: u32,
+
+    #[query]
+    query: Option<VecQuery>,
+}
+
+#[test]
+fn repeated_keys_query_1() {
+    let path: UserDetailRepeatedKeysPath = "/users/1024?friend_ids=1".parse().unwrap();
+    assert_eq!(
+        path,
+        UserDetailRepeatedKeysPath {
+            user_id: 1024,
+            query: Some(VecQuery {
+                friend_ids: vec![1],
+            }),
+        }
+    );
+    assert_eq!(path.to_string(), "/users/1024?friend_ids=1");
+}
+
+#[test]
+fn repeated_keys_query_2() {
+    let path: UserDetailRepeatedKeysPath = "/users/1024?friend_ids=1&friend_ids=20&friend_ids=33"
+        .parse()
+        .unwrap();
+    assert_eq!(
+        path,
+        UserDetailRepeatedKeysPath {
+            user_id: 1024,
+            query: Some(VecQuery {
+                friend_ids: vec![1, 20, 33],
+            }),
+        }
+    );
+    assert_eq!(
+        path.to_string(),
+        "/users/1024?friend_ids=1&friend_ids=20&friend_ids=33"
+    );
+}
+
+#[test]
+fn repeated_keys_query_3() {
+    let path: UserDetailRepeatedKeysPath = "/users/1024?friend_ids[]=1".parse().unwrap();
+    assert_eq!(
+        path,
+        UserDetailRepeatedKeysPath {
+            user_id: 1024,
+            query: None,
+        }
+    );
+    assert_eq!(path.to_string(), "/users/1024");
+}
+
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/users/:user_id")]
+struct UserDetailCommaSeparatedPath {
+    user_id: u32,
+
+    #[query(strict, codec = "rs_frame::codec::CommaSeparated")]
+    query: Option<VecQuery>,
+}
+
+#[test]
+fn comma_separated_query_1() {
+    let path: UserDetailCommaSeparatedPath = "/users/1024?friend_ids=1,20,33".parse().unwrap();
+    assert_eq!(
+        path,
+        UserDetailCommaSeparatedPath {
+            user_id: 1024,
+            query: Some(VecQuery {
+                friend_ids: vec![1, 20, 33],
+            }),
+        }
+    );
+    assert_eq!(path.to_string(), "/users/1024?friend_ids=1,20,33");
+}
+
+#[test]
+fn comma_separated_query_2() {
+    let path: UserDetailCommaSeparatedPath = "/users/1024?friend_ids=".parse().unwrap();
+    assert_eq!(
+        path,
+        UserDetailCommaSeparatedPath {
+            user_id: 1024,
+            query: Some(VecQuery { friend_ids: vec![] }),
+        }
+    );
+    assert_eq!(path.to_string(), "/users/1024?friend_ids=");
+}
+
+#[test]
+fn comma_separated_query_3() {
+    let path: Result<UserDetailCommaSeparatedPath, _> = "/users/1024?friend_ids=1,x".parse();
+    match path {
+        Err(PathParseErr::QueryParseErr(e)) => {
+            assert_eq!(e.field, "query");
+            assert_eq!(e.query, "friend_ids=1,x");
+        }
+        other => panic!("expected a QueryParseErr, got {:?}", other),
+    }
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+struct TagsQuery {
+    tags: Vec<String>,
+}
+
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/tags")]
+struct CommaSeparatedTagsPath {
+    #[query(codec = "rs_frame::codec::CommaSeparated", encoding = "rfc3986")]
+    query: TagsQuery,
+}
+
+#[test]
+fn comma_separated_query_escapes_commas() {
+    let path = CommaSeparatedTagsPath {
+        query: TagsQuery {
+            tags: vec!["a,b".to_string(), "c d".to_string()],
+        },
+    };
+
+    assert_eq!(path.to_string(), "/tags?tags=a%2Cb,c%20d");
+    assert_eq!(
+        path.to_string().parse::<CommaSeparatedTagsPath>().unwrap(),
+        path
+    );
+}
+
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/p/:project_id/submissions")]
+#[query(strict, codec = "rs_frame::codec::FormUrlEncoded")]
+struct FormSubmissionsPath {
+    project_id: String,
+
+    #[query]
+    query: Option<SubmissionsQuery>,
+}
+
+#[test]
+fn form_urlencoded_query_1() {
+    let path: FormSubmissionsPath = "/p/43/submissions?column=first+name&direction=desc"
+        .parse()
+        .unwrap();
+    assert_eq!(
+        path,
+        FormSubmissionsPath {
+            project_id: "43".to_string(),
+            query: Some(SubmissionsQuery {
+                column: Some("first name".to_string()),
+                direction: Some(SortDirection::Desc),
+                keyword: None,
+            }),
+        }
+    );
+    assert_eq!(
+        path.to_string(),
+        "/p/43/submissions?column=first+name&direction=desc"
+    );
+}
+
+#[test]
+fn form_urlencoded_query_2() {
+    let path = FormSubmissionsPath {
+        project_id: "43".to_string(),
+        query: Some(SubmissionsQuery {
+            column: None,
+            direction: None,
+            keyword: Some("1+1=2 & more".to_string()),
+        }),
+    };
+
+    assert_eq!(
+        path.to_string(),
+        "/p/43/submissions?keyword=1%2B1%3D2+%26+more"
+    );
+    assert_eq!(
+        path.to_string().parse::<FormSubmissionsPath>().unwrap(),
+        path
+    );
+}
+
+#[test]
+fn form_urlencoded_query_3() {
+    let path: Result<FormSubmissionsPath, _> = "/p/43/submissions?direction=sideways".parse();
+    match path {
+        Err(PathParseErr::QueryParseErr(e)) => {
+            assert_eq!(e.field, "query");
+        }
+        other => panic!("expected a QueryParseErr, got {:?}", other),
+    }
+}
+
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/users/:user_id")]
+#[query(codec = "rs_frame::codec::RepeatedKeys")]
+struct OverlappingRepeatedKeysPath {
+    user_id: u32,
+
+    #[query]
+    query: VecQuery,
+
+    #[query]
+    other_query: Option<VecQuery>,
+}
+
+#[test]
+fn merged_queries_compare_repeated_keys() {
+    let mut path: OverlappingRepeatedKeysPath =
+        "/users/3?friend_ids=1&friend_ids=2".parse().unwrap();
+    assert_eq!(path.to_string(), "/users/3?friend_ids=1&friend_ids=2");
+
+    path.other_query = Some(VecQuery {
+        friend_ids: vec![1],
+    });
+
+    match path.write_query(&mut String::new()) {
+        Err(QueryWriteErr::Conflict { key, first, second }) => {
+            assert_eq!(key, "friend_ids");
+            assert_eq!(first, "1,2");
+            assert_eq!(second, "1");
+        }
+        other => panic!("expected a Conflict, got {:?}", other),
+    }
+}
+
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/search")]
+#[query(encoding = "rfc3986")]
+struct Rfc3986SearchPath {
+    #[query_param(rename = "q")]
+    keyword: String,
+}
+
+#[test]
+fn rfc3986_query_keeps_plus_sign() {
+    let path: Rfc3986SearchPath = "/search?q=c++%20lang".parse().unwrap();
+    assert_eq!(
+        path,
+        Rfc3986SearchPath {
+            keyword: "c++ lang".to_string(),
+        }
+    );
+    assert_eq!(path.to_string(), "/search?q=c%2B%2B%20lang");
+    assert_eq!(path.to_string().parse::<Rfc3986SearchPath>().unwrap(), path);
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+struct KeywordQuery {
+    keyword: Option<String>,
+}
+
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/search")]
+struct EncodingPerFieldPath {
+    #[query(encoding = "rfc3986")]
+    query: Option<KeywordQuery>,
+}
+
+#[test]
+fn rfc3986_query_with_serde_qs() {
+    let path: EncodingPerFieldPath = "/search?keyword=a+b%20c".parse().unwrap();
+    assert_eq!(
+        path,
+        EncodingPerFieldPath {
+            query: Some(KeywordQuery {
+                keyword: Some("a+b c".to_string()),
+            }),
+        }
+    );
+    assert_eq!(path.to_string(), "/search?keyword=a%2Bb%20c");
+    assert_eq!(
+        path.to_string().parse::<EncodingPerFieldPath>().unwrap(),
+        path
+    );
+}
+
+#[test]
+fn form_query_param_plus_as_space() {
+    let path = SearchPath {
+        keyword: "a+b c".to_string(),
+        page: None,
+    };
+
+    assert_eq!(path.to_string(), "/search?q=a%2Bb+c");
+    assert_eq!(path.to_string().parse::<SearchPath>().unwrap(), path);
+}
+
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/search")]
+#[query(deny_unknown)]
+struct DenyUnknownSearchPath {
+    #[query_param(rename = "q")]
+    keyword: Option<String>,
+
+    #[query]
+    query: Option<LimitOffsetQuery>,
+}
+
+#[test]
+fn deny_unknown_known_keys() {
+    let path: DenyUnknownSearchPath = "/search?q=cats&limit=10".parse().unwrap();
+    assert_eq!(
+        path,
+        DenyUnknownSearchPath {
+            keyword: Some("cats".to_string()),
+            query: Some(LimitOffsetQuery {
+                limit: Some(10),
+                offset: None,
+            }),
+        }
+    );
+}
+
+#[test]
+fn deny_unknown_rejects_unknown_keys() {
+    let path: Result<DenyUnknownSearchPath, _> =
+        "/search?q=cats&utm_source=newsletter&limit=10&ref=a&ref=b".parse();
+    match path {
+        Err(PathParseErr::UnknownQueryKeys(ref keys)) => {
+            assert_eq!(keys, &["utm_source".to_string(), "ref".to_string()]);
+        }
+        ref other => panic!("expected UnknownQueryKeys, got {:?}", other),
+    }
+    assert_eq!(
+        path.unwrap_err().to_string(),
+        "the query string has unknown keys: `utm_source`, `ref`"
+    );
+}
+
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/p/:project_id/submissions")]
+struct RestSubmissionsPath {
+    project_id: String,
+
+    #[query]
+    query: Option<SubmissionsQuery>,
+
+    #[query(rest)]
+    extra: std::collections::BTreeMap<String, String>,
+}
+
+#[test]
+fn rest_collects_unknown_params() {
+    let path: RestSubmissionsPath =
+        "/p/43/submissions?column=name&utm_source=news+letter&utm_medium=email"
+            .parse()
+            .unwrap();
+    let mut extra = std::collections::BTreeMap::new();
+    extra.insert("utm_source".to_string(), "news letter".to_string());
+    extra.insert("utm_medium".to_string(), "email".to_string());
+
+    assert_eq!(
+        path,
+        RestSubmissionsPath {
+            project_id: "43".to_string(),
+            query: Some(SubmissionsQuery {
+                column: Some("name".to_string()),
+                direction: None,
+                keyword: None,
+            }),
+            extra,
+        }
+    );
+    assert_eq!(
+        path.to_string(),
+        "/p/43/submissions?column=name&utm_medium=email&utm_source=news+letter"
+    );
+}
+
+#[test]
+fn rest_map_keeps_first_value() {
+    let path: RestSubmissionsPath = "/p/43/submissions?tag=a&tag=b".parse().unwrap();
+    assert_eq!(path.extra.get("tag").map(String::as_str), Some("a"));
+    assert_eq!(path.to_string(), "/p/43/submissions?tag=a");
+}
+
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/p/:project_id/tagged")]
+struct RestPairsPath {
+    project_id: String,
+
+    #[query]
+    query: Option<SubmissionsQuery>,
+
+    #[query(rest)]
+    extra: Vec<(String, String)>,
+}
+
+#[test]
+fn rest_pairs_keep_every_value() {
+    let url = "/p/43/tagged?tag=a&tag=b&utm_source=news";
+    let path: RestPairsPath = url.parse().unwrap();
+
+    assert_eq!(
+        path.extra,
+        vec![
+            ("tag".to_string(), "a".to_string()),
+            ("tag".to_string(), "b".to_string()),
+            ("utm_source".to_string(), "news".to_string()),
+        ]
+    );
+    assert_eq!(path.to_string(), "/p/43/tagged?tag=a&tag=b&utm_source=news");
+    assert_eq!(path.try_to_string().unwrap(), path.to_string());
+    assert_eq!(path.to_string().parse::<RestPairsPath>().unwrap(), path);
+}
+
+#[test]
+fn rest_empty_without_query_string() {
+    let path: RestSubmissionsPath = "/p/43/submissions".parse().unwrap();
+    assert!(path.extra.is_empty());
+    assert_eq!(path.to_string(), "/p/43/submissions");
+}
+
+#[test]
+fn canonical_url_sorts_and_drops_defaults() {
+    let path: UsersListWithQuery = "/users?limit=10&keyword=cats&friends_only=false&offset="
+        .parse()
+        .unwrap();
+    let reordered: UsersListWithQuery = "/users?keyword=cats&limit=10".parse().unwrap();
+
+    assert_eq!(path, reordered);
+    assert_eq!(
+        path.to_string(),
+        "/users?limit=10&keyword=cats&friends_only=false"
+    );
+    assert_eq!(path.canonical_url(), "/users?keyword=cats&limit=10");
+    assert_eq!(reordered.canonical_url(), path.canonical_url());
+    assert_eq!(
+        path.canonical_url().parse::<UsersListWithQuery>().unwrap(),
+        path
+    );
+}
+
+#[test]
+fn canonical_url_keeps_non_default_values() {
+    let path: UsersListWithQuery = "/users?friends_only=true".parse().unwrap();
+    assert_eq!(path.canonical_url(), "/users?friends_only=true");
+}
+
+#[test]
+fn canonical_url_without_query() {
+    assert_eq!(UsersListPath {}.canonical_url(), "/users");
+    assert_eq!(
+        "/users/5?friends_only=false"
+            .parse::<Route>()
+            .unwrap()
+            .canonical_url(),
+        "/users/5"
+    );
+}
+
+#[test]
+fn canonical_url_keeps_repeated_keys_in_order() {
+    let path: UserDetailRepeatedKeysPath = "/users/1?friend_ids=3&friend_ids=1".parse().unwrap();
+    assert_eq!(path.canonical_url(), "/users/1?friend_ids=3&friend_ids=1");
+}
+
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/sorted/:id")]
+#[query(canonical)]
+struct CanonicalPath {
+    id: u32,
+
+    #[query_param]
+    z: Option<String>,
+
+    #[query]
+    query: Option<LimitOffsetQuery>,
+
+    #[query_param]
+    a: Option<String>,
+}
+
+#[test]
+fn canonical_display() {
+    let path = CanonicalPath {
+        id: 1,
+        z: Some("last".to_string()),
+        query: Some(LimitOffsetQuery {
+            limit: Some(10),
+            offset: None,
+        }),
+        a: Some(String::new()),
+    };
+
+    // `a` is kept, since leaving it out would read back as `None`
+    assert_eq!(path.to_string(), "/sorted/1?a=&limit=10&z=last");
+    assert_eq!(path.query_string(), Some("a=&limit=10&z=last".to_string()));
+    assert_eq!(path.canonical_url(), path.to_string());
+    assert_eq!(path.to_string().parse::<CanonicalPath>().unwrap(), path);
+}
+
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/users")]
+#[query(canonical)]
+struct CanonicalUsersListPath {
+    #[query]
+    query: UserListQuery,
+}
+
+fn assert_canonical_url_round_trips<T>(urls: &[&str])
+where
+    T: AppPath + std::str::FromStr<Err = PathParseErr> + std::fmt::Debug + PartialEq,
+{
+    for url in urls {
+        let path: T = url.parse().unwrap();
+        let canonical_url = path.canonical_url();
+
+        assert_eq!(canonical_url.parse::<T>().unwrap(), path, "url: {}", url);
+        assert_eq!(path.to_string().parse::<T>().unwrap(), path, "url: {}", url);
+    }
+}
+
+#[test]
+fn canonical_url_round_trips() {
+    let path: UsersListWithQuery = "/users?friends_only=false".parse().unwrap();
+    assert_eq!(path.canonical_url(), "/users?");
+
+    let path: CanonicalUsersListPath = "/users?friends_only=false".parse().unwrap();
+    assert_eq!(path.to_string(), "/users?");
+
+    let urls = [
+        "/users?",
+        "/users?friends_only=false",
+        "/users?friends_only=true&limit=3",
+        "/users?keyword=&offset=0",
+    ];
+    assert_canonical_url_round_trips::<UsersListWithQuery>(&urls);
+    assert_canonical_url_round_trips::<CanonicalUsersListPath>(&urls);
+
+    assert_canonical_url_round_trips::<CanonicalPath>(&[
+        "/sorted/1",
+        "/sorted/1?a=",
+        "/sorted/1?z=&a=x&limit=",
+        "/sorted/1?offset=0&limit=5",
+    ]);
+}
@@ -0,0 +1,337 @@
+use rs_frame::{AppPath, PathParseErr, RouteConflict, Router};
+use serde::{Deserialize, Serialize};
+
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/users")]
+struct UsersListPath {}
+
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/users/:user_id")]
+struct UserDetailPath {
+    user_id: u32,
+}
+
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/users/:username")]
+struct UserByNamePath {
+    username: String,
+}
+
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/users/new")]
+struct NewUserPath {}
+
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/users/:user_id/posts/:page?")]
+struct UserPostsPath {
+    user_id: u32,
+    page: Option<u32>,
+}
+
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/files/*file_path")]
+struct FilePath {
+    file_path: String,
+}
+
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/files/:name/info")]
+struct FileInfoPath {
+    name: String,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct SearchQuery {
+    keyword: String,
+}
+
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/search")]
+struct SearchPath {
+    #[query]
+    query: SearchQuery,
+}
+
+#[derive(Debug, PartialEq)]
+enum Page {
+    UsersList,
+    UserDetail(u32),
+    UserByName(String),
+    NewUser,
+    UserPosts(u32, Option<u32>),
+    File(String),
+    FileInfo(String),
+    Search(String),
+}
+
+fn router() -> Router<Page> {
+    let mut router = Router::new();
+
+    router.add(|_: UsersListPath| Page::UsersList);
+    router.add(|path: UserDetailPath| Page::UserDetail(path.user_id));
+    router.add(|path: UserByNamePath| Page::UserByName(path.username));
+    router.add(|_: NewUserPath| Page::NewUser);
+    router.add(|path: UserPostsPath| Page::UserPosts(path.user_id, path.page));
+    router.add(|path: FilePath| Page::File(path.file_path));
+    router.add(|path: FileInfoPath| Page::FileInfo(path.name));
+    router.add(|path: SearchPath| Page::Search(path.query.keyword));
+
+    router
+}
+
+#[test]
+fn static_route() {
+    assert_eq!(router().route("/users").unwrap(), Page::UsersList);
+}
+
+#[test]
+fn param_route() {
+    assert_eq!(router().route("/users/12").unwrap(), Page::UserDetail(12));
+}
+
+#[test]
+fn static_beats_param() {
+    // NewUserPath was added after both param routes, but still wins
+    assert_eq!(router().route("/users/new").unwrap(), Page::NewUser);
+}
+
+#[test]
+fn falls_through_when_param_fails_to_parse() {
+    assert_eq!(
+        router().route("/users/bob").unwrap(),
+        Page::UserByName("bob".to_string())
+    );
+}
+
+#[test]
+fn optional_param() {
+    let router = router();
+
+    assert_eq!(
+        router.route("/users/12/posts").unwrap(),
+        Page::UserPosts(12, None)
+    );
+    assert_eq!(
+        router.route("/users/12/posts/3").unwrap(),
+        Page::UserPosts(12, Some(3))
+    );
+}
+
+#[test]
+fn param_beats_splat() {
+    let router = router();
+
+    assert_eq!(
+        router.route("/files/taxes.pdf/info").unwrap(),
+        Page::FileInfo("taxes.pdf".to_string())
+    );
+    assert_eq!(
+        router.route("/files/2019/taxes.pdf").unwrap(),
+        Page::File("2019/taxes.pdf".to_string())
+    );
+    assert_eq!(
+        router.route("/files/info").unwrap(),
+        Page::File("info".to_string())
+    );
+}
+
+#[test]
+fn query_string() {
+    assert_eq!(
+        router().route("/search?keyword=cats").unwrap(),
+        Page::Search("cats".to_string())
+    );
+}
+
+#[test]
+fn fragment_ignored() {
+    let router = router();
+
+    assert_eq!(router.route("/users/1#top").unwrap(), Page::UserDetail(1));
+    assert_eq!(
+        router.route("/search?keyword=cats#results").unwrap(),
+        Page::Search("cats".to_string())
+    );
+    assert_eq!(router.route("/users#").unwrap(), Page::UsersList);
+}
+
+#[test]
+fn no_matches() {
+    let router = router();
+
+    for app_path in &[
+        "/nowhere",
+        "/users/12/friends",
+        "/users/",
+        "users",
+        "",
+        "/files",
+    ] {
+        assert!(matches!(
+            router.route(app_path),
+            Err(PathParseErr::NoMatches)
+        ));
+    }
+}
+
+#[test]
+fn error_from_matching_route() {
+    assert!(matches!(
+        router().route("/users/12/posts/first"),
+        Err(PathParseErr::ParamParseErr(_))
+    ));
+    assert!(matches!(
+        router().route("/search"),
+        Err(PathParseErr::NoQueryString)
+    ));
+}
+
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/dates/:date(\\d+/\\d+)")]
+struct DatesPath {
+    date: String,
+}
+
+#[test]
+fn constraint_spanning_segments() {
+    let mut router: Router<String> = Router::new();
+    router.add(|path: DatesPath| path.date);
+    router.add(|path: UserDetailPath| path.user_id.to_string());
+
+    assert_eq!(router.route("/dates/12/34").unwrap(), "12/34");
+    assert!(matches!(
+        router.route("/dates/12"),
+        Err(PathParseErr::NoMatches)
+    ));
+    assert_eq!(router.route("/users/5").unwrap(), "5");
+}
+
+#[derive(AppPath, Debug, PartialEq)]
+#[path("/docs/(draft)/:id")]
+struct DraftDocPath {
+    id: u32,
+}
+
+#[test]
+fn static_parens() {
+    let mut router: Router<u32> = Router::new();
+    router.add(|path: DraftDocPath| path.id);
+
+    assert_eq!(router.route("/docs/(draft)/5").unwrap(), 5);
+    assert!(matches!(
+        router.route("/docs/draft/5"),
+        Err(PathParseErr::NoMatches)
+    ));
+}
+
+#[derive(AppPath, Debug, PartialEq)]
+enum Route {
+    #[path("/users/:user_id")]
+    UserDetail {
+        user_id: u32,
+    },
+
+    NewUser(NewUserPath),
+}
+
+#[test]
+fn add_path() {
+    let mut router: Router<Route> = Router::new();
+    router.add_path::<Route>();
+
+    assert_eq!(
+        router.route("/users/12").unwrap(),
+        Route::UserDetail { user_id: 12 }
+    );
+    assert_eq!(
+        router.route("/users/new").unwrap(),
+        Route::NewUser(NewUserPath {})
+    );
+}
+
+#[test]
+fn conflicts() {
+    let conflicts = router().conflicts();
+
+    assert_eq!(
+        conflicts,
+        vec![
+            RouteConflict {
+                winner: "/users/:user_id".to_string(),
+                loser: "/users/:username".to_string(),
+                example: "/users/1".to_string(),
+            },
+            RouteConflict {
+                winner: "/users/new".to_string(),
+                loser: "/users/:user_id".to_string(),
+                example: "/users/new".to_string(),
+            },
+            RouteConflict {
+                winner: "/users/new".to_string(),
+                loser: "/users/:username".to_string(),
+                example: "/users/new".to_string(),
+            },
+            RouteConflict {
+                winner: "/files/:name/info".to_string(),
+                loser: "/files/*file_path".to_string(),
+                example: "/files/1/info".to_string(),
+            },
+        ]
+    );
+    assert_eq!(
+        conflicts[1].to_string(),
+        "`/users/new` matches both `/users/new` and `/users/:user_id`, `/users/new` wins"
+    );
+}
+
+#[derive(AppPath)]
+enum ShadowedRoute {
+    #[path("/users/:id")]
+    Detail { id: String },
+
+    #[path("/users/new")]
+    New,
+
+    #[path("/users")]
+    List,
+}
+
+#[test]
+fn conflicts_within_enum() {
+    let mut router = Router::new();
+    router.add(|_: ShadowedRoute| ());
+
+    assert_eq!(
+        router.conflicts(),
+        vec![RouteConflict {
+            winner: "/users/:id".to_string(),
+            loser: "/users/new".to_string(),
+            example: "/users/new".to_string(),
+        }]
+    );
+}
+
+#[derive(AppPath)]
+#[path("/articles/:id(\\d+)")]
+struct ArticlePath {
+    id: u32,
+}
+
+#[derive(AppPath)]
+#[path("/articles/:slug([a-z-]+)")]
+struct ArticleBySlugPath {
+    slug: String,
+}
+
+#[test]
+fn no_conflicts() {
+    let mut router = Router::new();
+
+    router.add(|_: UsersListPath| ());
+    router.add(|_: UserPostsPath| ());
+    router.add(|_: SearchPath| ());
+    router.add(|_: ArticlePath| ());
+    router.add(|_: ArticleBySlugPath| ());
+
+    assert_eq!(router.conflicts(), vec![]);
+}
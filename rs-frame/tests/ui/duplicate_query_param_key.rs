@@ -0,0 +1,13 @@
+use rs_frame::AppPath;
+
+#[derive(AppPath)]
+#[path("/search")]
+struct SearchPath {
+    #[query_param]
+    q: String,
+
+    #[query_param(rename = "q")]
+    keyword: Option<String>,
+}
+
+fn main() {}
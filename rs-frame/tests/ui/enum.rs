@@ -0,0 +1,11 @@
+use rs_frame::AppPath;
+
+#[derive(AppPath)]
+enum Route {
+    #[path("/users")]
+    Users,
+
+    UserDetail { user_id: u32 },
+}
+
+fn main() {}
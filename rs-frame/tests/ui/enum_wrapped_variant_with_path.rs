@@ -0,0 +1,13 @@
+use rs_frame::AppPath;
+
+#[derive(AppPath)]
+#[path("/users")]
+struct UsersPath {}
+
+#[derive(AppPath)]
+enum Route {
+    #[path("/users")]
+    Users(UsersPath),
+}
+
+fn main() {}
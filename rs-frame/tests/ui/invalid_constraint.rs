@@ -0,0 +1,9 @@
+use rs_frame::AppPath;
+
+#[derive(AppPath)]
+#[path("/users/:user_id([0-9)")]
+struct UserDetailPath {
+    user_id: u64,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use rs_frame::AppPath;
+
+#[derive(AppPath)]
+#[path("/users/:_user_id")]
+struct UserDetailPath {
+    _user_id: u64,
+}
+
+fn main() {}
@@ -0,0 +1,16 @@
+use rs_frame::AppPath;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct UserListQuery {
+    limit: Option<u64>,
+}
+
+#[derive(AppPath)]
+#[path("/users")]
+struct UsersListPath {
+    #[query(lenient)]
+    query: UserListQuery,
+}
+
+fn main() {}
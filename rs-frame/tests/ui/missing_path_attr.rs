@@ -0,0 +1,6 @@
+use rs_frame::AppPath;
+
+#[derive(AppPath)]
+struct UsersListPath {}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use rs_frame::AppPath;
+
+#[derive(AppPath)]
+#[path("/users/田中/:user_id")]
+struct UserDetailPath {
+    user_id: u64,
+}
+
+fn main() {}
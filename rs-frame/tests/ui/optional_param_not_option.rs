@@ -0,0 +1,9 @@
+use rs_frame::AppPath;
+
+#[derive(AppPath)]
+#[path("/posts/:page?")]
+struct PostsPath {
+    page: u32,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use rs_frame::AppPath;
+
+#[derive(AppPath)]
+#[path("/users/:user_id/friends/:friend_id")]
+struct UserFriendPath {
+    user_id: u64,
+}
+
+fn main() {}
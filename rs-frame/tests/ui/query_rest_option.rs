@@ -0,0 +1,11 @@
+use rs_frame::AppPath;
+use std::collections::BTreeMap;
+
+#[derive(AppPath)]
+#[path("/users")]
+struct UsersListPath {
+    #[query(rest)]
+    extra: Option<BTreeMap<String, String>>,
+}
+
+fn main() {}
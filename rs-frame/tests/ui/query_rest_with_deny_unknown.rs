@@ -0,0 +1,12 @@
+use rs_frame::AppPath;
+use std::collections::BTreeMap;
+
+#[derive(AppPath)]
+#[path("/users")]
+#[query(deny_unknown)]
+struct UsersListPath {
+    #[query(rest)]
+    extra: BTreeMap<String, String>,
+}
+
+fn main() {}
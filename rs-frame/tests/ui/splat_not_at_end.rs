@@ -0,0 +1,9 @@
+use rs_frame::AppPath;
+
+#[derive(AppPath)]
+#[path("/files/*rest/edit")]
+struct FileEditPath {
+    rest: String,
+}
+
+fn main() {}
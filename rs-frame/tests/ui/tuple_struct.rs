@@ -0,0 +1,7 @@
+use rs_frame::AppPath;
+
+#[derive(AppPath)]
+#[path("/users/:user_id")]
+struct UserDetailPath(u64);
+
+fn main() {}
@@ -0,0 +1,10 @@
+use rs_frame::AppPath;
+
+#[derive(AppPath)]
+#[path("/search")]
+struct SearchPath {
+    #[query_param(name = "q")]
+    keyword: String,
+}
+
+fn main() {}
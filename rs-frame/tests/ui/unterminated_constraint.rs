@@ -0,0 +1,9 @@
+use rs_frame::AppPath;
+
+#[derive(AppPath)]
+#[path("/users/:user_id(\\d+")]
+struct UserDetailPath {
+    user_id: u64,
+}
+
+fn main() {}